@@ -1,41 +1,816 @@
-use eframe::{egui, NativeOptions};
-use egui::{CentralPanel, Slider, Ui};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use eframe::{NativeOptions, egui};
+use std::collections::BTreeMap;
+
+use egui::{CentralPanel, Color32, ComboBox, Grid, Pos2, Rect, Sense, Stroke, Ui, vec2};
+use trading_lib::{
+    AuctionEngine, BarKind, BookEvent, CandleSeries, Exchange, Execution, FulfillmentEngine,
+    InstrumentRules, Order, OrderId, Peg, PegReference, PostOnly, Price, PriceLevel, Side,
+    TimeInForce, Timestamp, Trade, TradingPhase, Trail, uncrossing_price,
+};
 
 fn main() -> eframe::Result<()> {
     let native_options = NativeOptions::default();
     eframe::run_native(
-        "My egui App",
+        "Basic trader",
         native_options,
-        Box::new(|cc| Ok(Box::new(MyApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(TraderApp::new(cc)))),
     )
 }
 
+fn now() -> Timestamp {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before 1970")
+        .as_millis() as Timestamp
+}
+
+/// Levels shown on each side of the depth ladder.
+const DEPTH: usize = 5;
+/// Most recent prints shown from the tape.
+const TAPE_ROWS: usize = 10;
+/// Most recent bars drawn on the chart.
+const CHART_BARS: usize = 40;
+/// Bar kinds the chart can show, by label.
+const BAR_KINDS: [(&str, BarKind); 8] = [
+    ("1s", BarKind::SECOND),
+    ("1m", BarKind::MINUTE),
+    ("5m", BarKind::FIVE_MINUTES),
+    ("1h", BarKind::HOUR),
+    ("1d", BarKind::DAY),
+    ("10 trades", BarKind::Ticks(10)),
+    ("100 lots", BarKind::Volume(100)),
+    ("1000 lots", BarKind::Volume(1000)),
+];
+
 #[derive(Default)]
-struct MyApp {
-    name: String,
-    age: u32,
+struct TraderApp {
+    exchange: Exchange,
+    // Symbol of the instrument orders are entered on.
+    symbol: String,
+    new_symbol: String,
+    // Blank for the default rules when listing `new_symbol`.
+    tick_size: String,
+    lot_size: String,
+    price: String,
+    quantity: String,
+    // Blank for an order without an owner.
+    account: String,
+    trigger: String,
+    trail: String,
+    order_id: String,
+    // `Gtd` is selected as `Gtd(0)`; the expiry comes from `expiry_seconds`.
+    time_in_force: TimeInForce,
+    expiry_seconds: String,
+    // Blank for an order shown in full.
+    display_quantity: String,
+    post_only: Option<PostOnly>,
+    // When set, Buy and Sell enter a pegged order and the price is ignored.
+    peg_reference: Option<PegReference>,
+    peg_offset: String,
+    peg_limit: String,
+    status: String,
+    // Index into `BAR_KINDS` of the bars charted.
+    bar_kind: usize,
+    // Bars per symbol, built from the executions of each engine run.
+    candles: BTreeMap<String, CandleSeries>,
 }
 
-impl MyApp {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+impl TraderApp {
+    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
         // Customize egui here if you want
         Self::default()
     }
+
+    /// Enters `order` in the selected book at the current time, so that its
+    /// expiry and any trades it makes are stamped correctly.
+    fn submit(&mut self, order: Order) -> Result<OrderId, &'static str> {
+        self.exchange.set_time(now());
+        self.exchange.submit(&self.symbol, order)
+    }
+
+    /// Tags `order` with the account typed in, if any. Sets the status and
+    /// returns `None` if the account is not a number.
+    fn with_account(&mut self, order: Order) -> Option<Order> {
+        match self.account.trim() {
+            "" => Some(order),
+            value => match value.parse() {
+                Ok(account) => Some(order.with_account(account)),
+                Err(_) => {
+                    self.status = "account must be a whole number".to_string();
+                    None
+                }
+            },
+        }
+    }
+
+    fn enter_order(&mut self, side: Side) {
+        let Ok(quantity) = self.quantity.trim().parse() else {
+            self.status = "quantity must be a whole number".to_string();
+            return;
+        };
+        let order = match self.peg_reference {
+            Some(reference) => {
+                let Ok(offset) = self.peg_offset.trim().parse() else {
+                    self.status = "peg offset must be a decimal number".to_string();
+                    return;
+                };
+                let limit = match self.peg_limit.trim() {
+                    "" => None,
+                    value => match value.parse() {
+                        Ok(limit) => Some(limit),
+                        Err(_) => {
+                            self.status = "peg limit must be a decimal number".to_string();
+                            return;
+                        }
+                    },
+                };
+                let peg = Peg {
+                    reference,
+                    offset,
+                    limit,
+                };
+                Order::pegged(side, peg, quantity)
+            }
+            None => {
+                let price: Price = match self.price.parse() {
+                    Ok(price) => price,
+                    Err(err) => {
+                        self.status = err.to_string();
+                        return;
+                    }
+                };
+                Order::new(side, price, quantity)
+            }
+        };
+        let time_in_force = match self.time_in_force {
+            TimeInForce::Gtd(_) => {
+                let Ok(seconds) = self.expiry_seconds.trim().parse::<Timestamp>() else {
+                    self.status = "expiry must be a whole number of seconds".to_string();
+                    return;
+                };
+                TimeInForce::Gtd(now() + seconds * 1000)
+            }
+            time_in_force => time_in_force,
+        };
+        let mut order = order.with_time_in_force(time_in_force);
+        if !self.display_quantity.trim().is_empty() {
+            let Ok(display_quantity) = self.display_quantity.trim().parse() else {
+                self.status = "display quantity must be a whole number".to_string();
+                return;
+            };
+            order = order.with_display_quantity(display_quantity);
+        }
+        if let Some(post_only) = self.post_only {
+            order = order.with_post_only(post_only);
+        }
+        let Some(order) = self.with_account(order) else {
+            return;
+        };
+        match self.submit(order) {
+            Ok(id) => {
+                self.status = format!("order {} entered", id);
+                self.run_engine();
+            }
+            Err(err) => self.status = format!("order rejected: {}", err),
+        }
+    }
+
+    fn enter_market_order(&mut self, side: Side) {
+        let Ok(quantity) = self.quantity.trim().parse() else {
+            self.status = "quantity must be a whole number".to_string();
+            return;
+        };
+        let Some(order) = self.with_account(Order::market(side, quantity)) else {
+            return;
+        };
+        match self.submit(order) {
+            Ok(id) => {
+                self.status = format!("market order {} entered", id);
+                self.run_engine();
+            }
+            Err(err) => self.status = format!("market order rejected: {}", err),
+        }
+    }
+
+    fn enter_trailing_stop_order(&mut self, side: Side) {
+        let Ok(quantity) = self.quantity.trim().parse() else {
+            self.status = "quantity must be a whole number".to_string();
+            return;
+        };
+        let trail: Trail = match self.trail.parse() {
+            Ok(trail) => trail,
+            Err(err) => {
+                self.status = err.to_string();
+                return;
+            }
+        };
+        let Some(order) = self.with_account(Order::trailing_stop(side, trail, quantity)) else {
+            return;
+        };
+        match self.submit(order) {
+            Ok(id) => self.status = format!("trailing stop order {} entered", id),
+            Err(err) => self.status = format!("trailing stop order rejected: {}", err),
+        }
+    }
+
+    fn enter_stop_order(&mut self, side: Side, is_stop_limit: bool) {
+        let (Ok(trigger), Ok(quantity)) =
+            (self.trigger.parse::<Price>(), self.quantity.trim().parse())
+        else {
+            self.status = "trigger must be a price and quantity a whole number".to_string();
+            return;
+        };
+        let order = if is_stop_limit {
+            let Ok(price) = self.price.parse::<Price>() else {
+                self.status = "price must be a decimal number".to_string();
+                return;
+            };
+            Order::stop_limit(side, trigger, price, quantity)
+        } else {
+            Order::stop(side, trigger, quantity)
+        };
+        let Some(order) = self.with_account(order) else {
+            return;
+        };
+        match self.submit(order) {
+            Ok(id) => {
+                self.status = format!("stop order {} entered", id);
+                // It may have triggered straight away.
+                self.run_engine();
+            }
+            Err(err) => self.status = format!("stop order rejected: {}", err),
+        }
+    }
+
+    fn cancel_order(&mut self, id: OrderId) {
+        self.status = match self.exchange.cancel(&self.symbol, id) {
+            Some(_) => format!("order {} cancelled", id),
+            None => format!("no order with id {}", id),
+        };
+    }
+
+    fn amend_order(&mut self) {
+        let (Ok(id), Ok(price), Ok(quantity)) = (
+            self.order_id.trim().parse(),
+            self.price.parse::<Price>(),
+            self.quantity.trim().parse(),
+        ) else {
+            self.status =
+                "order id and quantity must be whole numbers, price a decimal".to_string();
+            return;
+        };
+        match self.exchange.amend(&self.symbol, id, price, quantity) {
+            Ok(()) => {
+                self.status = format!("order {} amended", id);
+                // An amended price can cross the book.
+                self.run_engine();
+            }
+            Err(err) => self.status = format!("amend failed: {}", err),
+        }
+    }
+
+    fn end_session(&mut self) {
+        self.exchange.end_session();
+        self.status = "session ended".to_string();
+        self.show_events();
+    }
+
+    /// Rules typed in for a new instrument. Sets the status and returns
+    /// `None` if a field does not parse.
+    fn instrument_rules(&mut self) -> Option<InstrumentRules> {
+        let mut rules = InstrumentRules::default();
+        if !self.tick_size.trim().is_empty() {
+            match self.tick_size.trim().parse::<Price>() {
+                Ok(tick_size) if tick_size > Price::ZERO => rules.tick_size = tick_size,
+                _ => {
+                    self.status = "tick size must be a positive price".to_string();
+                    return None;
+                }
+            }
+        }
+        if !self.lot_size.trim().is_empty() {
+            match self.lot_size.trim().parse() {
+                Ok(lot_size) if lot_size > 0 => rules.lot_size = lot_size,
+                _ => {
+                    self.status = "lot size must be a positive whole number".to_string();
+                    return None;
+                }
+            }
+        }
+        Some(rules)
+    }
+
+    fn list_instrument(&mut self) {
+        let symbol = self.new_symbol.trim().to_uppercase();
+        let Some(rules) = self.instrument_rules() else {
+            return;
+        };
+        let mut book = Trade::new();
+        book.rules = rules;
+        match self.exchange.list_instrument(&symbol, book) {
+            Ok(()) => {
+                self.status = format!("listed {}", symbol);
+                self.symbol = symbol;
+                self.new_symbol.clear();
+            }
+            Err(err) => self.status = format!("cannot list {}: {}", symbol, err),
+        }
+    }
+
+    fn start_auction(&mut self) {
+        match self.exchange.book_mut(&self.symbol) {
+            Some(book) => {
+                book.phase = TradingPhase::Auction;
+                self.status = format!("auction started on {}", self.symbol);
+            }
+            None => self.status = "select an instrument first".to_string(),
+        }
+    }
+
+    fn uncross_auction(&mut self) {
+        self.exchange.set_time(now());
+        let Some(book) = self
+            .exchange
+            .book_mut(&self.symbol)
+            .filter(|book| book.phase == TradingPhase::Auction)
+        else {
+            self.status = "no auction in progress".to_string();
+            return;
+        };
+        let mut engine = AuctionEngine::new(book);
+        self.status = match engine.indicative_price() {
+            Some(price) => format!("uncrossed at {}", price),
+            None => "book was not crossed".to_string(),
+        };
+        let executed = engine.fulfill_all();
+        for execution in &executed {
+            self.status = format!(
+                "{}; executed {} (buy {} / sell {})",
+                self.status, execution.quantity, execution.buy_order_id, execution.sell_order_id
+            );
+        }
+        book.phase = TradingPhase::Continuous;
+        self.chart(&executed);
+        // Orders that could not take part, such as market orders, match now.
+        self.run_engine();
+    }
+
+    fn run_engine(&mut self) {
+        self.exchange.set_time(now());
+        let Some(mut engine) = self.exchange.engine(&self.symbol) else {
+            return;
+        };
+        let executed = engine.fulfill_all();
+        for execution in &executed {
+            self.status = format!(
+                "{}; executed {} @ {} (buy {} / sell {})",
+                self.status,
+                execution.quantity,
+                execution.price,
+                execution.buy_order_id,
+                execution.sell_order_id
+            );
+        }
+        self.chart(&executed);
+        self.show_events();
+    }
+
+    /// Adds executions on the selected instrument to its bars.
+    fn chart(&mut self, executed: &[Execution]) {
+        let kind = BAR_KINDS[self.bar_kind].1;
+        let series = self
+            .candles
+            .entry(self.symbol.clone())
+            .or_insert_with(|| CandleSeries::new(kind).expect("chart bar sizes are positive"));
+        for execution in executed {
+            series.record(execution);
+        }
+    }
+
+    /// Rebuilds every instrument's bars from its tape, after the bar kind
+    /// changes.
+    fn rechart(&mut self) {
+        let kind = BAR_KINDS[self.bar_kind].1;
+        self.candles = self
+            .exchange
+            .instruments()
+            .map(|symbol| {
+                let prints = self.exchange.book(symbol).unwrap().tape().prints();
+                let series = CandleSeries::from_executions(kind, prints)
+                    .expect("chart bar sizes are positive");
+                (symbol.to_string(), series)
+            })
+            .collect();
+    }
+
+    fn show_events(&mut self) {
+        for (symbol, event) in self.exchange.take_events() {
+            match event {
+                BookEvent::Expired { order, reason } => {
+                    self.status = format!(
+                        "{}; expired {} order {} ({} left, {:?})",
+                        self.status,
+                        symbol,
+                        order.id,
+                        order.total_quantity(),
+                        reason
+                    );
+                }
+                BookEvent::Triggered { order } => {
+                    self.status = format!(
+                        "{}; triggered {} stop order {} ({} {:?})",
+                        self.status,
+                        symbol,
+                        order.id,
+                        order.total_quantity(),
+                        order.kind
+                    );
+                }
+            }
+        }
+    }
+
+    /// Shows the best bid and offer and a depth ladder of the top levels.
+    fn show_depth(ui: &mut Ui, book: &Trade) {
+        let level1 = book.level1();
+        let show = |level: Option<PriceLevel>| {
+            level.map_or("-".to_string(), |level| {
+                format!("{} @ {}", level.quantity, level.price)
+            })
+        };
+        ui.label(format!(
+            "Best bid {}, best offer {}, spread {}",
+            show(level1.bid),
+            show(level1.offer),
+            level1
+                .spread()
+                .map_or("-".to_string(), |spread| spread.to_string())
+        ));
+
+        let level2 = book.level2(DEPTH);
+        Grid::new("depth").striped(true).show(ui, |ui| {
+            ui.label("Bid orders");
+            ui.label("Bid qty");
+            ui.label("Bid");
+            ui.label("Offer");
+            ui.label("Offer qty");
+            ui.label("Offer orders");
+            ui.end_row();
+            for row in 0..level2.bids.len().max(level2.offers.len()) {
+                match level2.bids.get(row) {
+                    Some(level) => {
+                        ui.label(level.order_count.to_string());
+                        ui.label(level.quantity.to_string());
+                        ui.label(level.price.to_string());
+                    }
+                    None => {
+                        ui.label("");
+                        ui.label("");
+                        ui.label("");
+                    }
+                }
+                if let Some(level) = level2.offers.get(row) {
+                    ui.label(level.price.to_string());
+                    ui.label(level.quantity.to_string());
+                    ui.label(level.order_count.to_string());
+                }
+                ui.end_row();
+            }
+        });
+    }
+
+    /// Shows the session statistics and the most recent prints, newest
+    /// first.
+    fn show_tape(ui: &mut Ui, book: &Trade) {
+        let stats = book.tape().stats();
+        let show = |price: Option<Price>| price.map_or("-".to_string(), |price| price.to_string());
+        ui.label(format!(
+            "Last {}  Open {}  High {}  Low {}  Volume {}  Turnover {}  Trades {}  VWAP {}",
+            show(stats.last),
+            show(stats.open),
+            show(stats.high),
+            show(stats.low),
+            stats.volume,
+            stats.turnover,
+            stats.trade_count,
+            show(stats.vwap())
+        ));
+
+        Grid::new("tape").striped(true).show(ui, |ui| {
+            ui.label("Time");
+            ui.label("Price");
+            ui.label("Quantity");
+            ui.label("Aggressor");
+            ui.end_row();
+            for print in book.tape().prints().iter().rev().take(TAPE_ROWS) {
+                ui.label(print.timestamp.to_string());
+                ui.label(print.price.to_string());
+                ui.label(print.quantity.to_string());
+                ui.label(format!("{:?}", print.aggressor));
+                ui.end_row();
+            }
+        });
+    }
+
+    /// Draws the most recent bars as candlesticks: green when the close is
+    /// at or above the open, red otherwise.
+    fn show_chart(ui: &mut Ui, series: Option<&CandleSeries>) {
+        let (response, painter) =
+            ui.allocate_painter(vec2(ui.available_width(), 160.0), Sense::hover());
+        let area = response.rect;
+        painter.rect_stroke(
+            area,
+            0.0,
+            Stroke::new(1.0, Color32::GRAY),
+            egui::StrokeKind::Inside,
+        );
+
+        let bars: Vec<_> = series.map_or(Vec::new(), |series| series.history().collect());
+        let bars = &bars[bars.len().saturating_sub(CHART_BARS)..];
+        let (Some(low), Some(high)) = (
+            bars.iter().map(|bar| bar.low).min(),
+            bars.iter().map(|bar| bar.high).max(),
+        ) else {
+            return;
+        };
+        let range = (high.units() - low.units()).max(1) as f32;
+        let y = |price: Price| {
+            area.bottom() - (price.units() - low.units()) as f32 / range * area.height()
+        };
+        let width = area.width() / CHART_BARS as f32;
+        for (index, bar) in bars.iter().enumerate() {
+            let x = area.left() + width * (index as f32 + 0.5);
+            let color = if bar.close >= bar.open {
+                Color32::from_rgb(40, 160, 80)
+            } else {
+                Color32::from_rgb(200, 60, 60)
+            };
+            painter.line_segment(
+                [Pos2::new(x, y(bar.high)), Pos2::new(x, y(bar.low))],
+                Stroke::new(1.0, color),
+            );
+            let body = Rect::from_two_pos(
+                Pos2::new(x - width * 0.35, y(bar.open)),
+                Pos2::new(x + width * 0.35, y(bar.close)),
+            );
+            painter.rect_filled(body.expand2(vec2(0.0, 0.5)), 0.0, color);
+        }
+    }
+
+    /// Lists orders with a cancel button per order. Returns the id of the
+    /// order whose cancel button was clicked.
+    fn show_orders<'a>(
+        ui: &mut Ui,
+        title: &str,
+        orders: impl Iterator<Item = &'a Order>,
+    ) -> Option<OrderId> {
+        let mut cancelled = None;
+        ui.vertical(|ui| {
+            ui.strong(title);
+            Grid::new(title).striped(true).show(ui, |ui| {
+                ui.label("Id");
+                ui.label("Price");
+                ui.label("Quantity");
+                ui.label("Trigger");
+                ui.label("TIF");
+                ui.end_row();
+                for order in orders {
+                    ui.label(order.id.to_string());
+                    ui.label(order.price.to_string());
+                    ui.label(order.quantity.to_string());
+                    ui.label(order.trigger().map_or(String::new(), |t| t.to_string()));
+                    ui.label(format!("{:?}", order.time_in_force));
+                    if ui.button("Cancel").clicked() {
+                        cancelled = Some(order.id);
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+        cancelled
+    }
 }
 
-impl eframe::App for MyApp {
-    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+impl eframe::App for TraderApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         CentralPanel::default().show(ctx, |ui| {
-            ui.heading("My egui Application");
+            ui.heading("Basic trader");
             ui.horizontal(|ui| {
-                ui.label("Your name: ");
-                ui.text_edit_singleline(&mut self.name);
+                ui.label("Instrument: ");
+                ComboBox::from_id_salt("instrument")
+                    .selected_text(&self.symbol)
+                    .show_ui(ui, |ui| {
+                        for symbol in self.exchange.instruments() {
+                            ui.selectable_value(&mut self.symbol, symbol.to_string(), symbol);
+                        }
+                    });
+                ui.text_edit_singleline(&mut self.new_symbol);
+                ui.label("Tick size: ");
+                ui.text_edit_singleline(&mut self.tick_size);
+                ui.label("Lot size: ");
+                ui.text_edit_singleline(&mut self.lot_size);
+                if ui.button("List instrument").clicked() {
+                    self.list_instrument();
+                }
             });
-            ui.add(Slider::new(&mut self.age, 0..=120).text("Age"));
-            if ui.button("Increment Age").clicked() {
-                self.age += 1;
+            ui.horizontal(|ui| {
+                ui.label("Price: ");
+                ui.text_edit_singleline(&mut self.price);
+                ui.label("Quantity: ");
+                ui.text_edit_singleline(&mut self.quantity);
+                ui.label("Account: ");
+                ui.text_edit_singleline(&mut self.account);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Time in force: ");
+                ComboBox::from_id_salt("time_in_force")
+                    .selected_text(format!("{:?}", self.time_in_force))
+                    .show_ui(ui, |ui| {
+                        for choice in [
+                            TimeInForce::Gtc,
+                            TimeInForce::Ioc,
+                            TimeInForce::Fok,
+                            TimeInForce::Day,
+                            TimeInForce::Gtd(0),
+                        ] {
+                            ui.selectable_value(
+                                &mut self.time_in_force,
+                                choice,
+                                format!("{:?}", choice),
+                            );
+                        }
+                    });
+                if matches!(self.time_in_force, TimeInForce::Gtd(_)) {
+                    ui.label("Expires in (s): ");
+                    ui.text_edit_singleline(&mut self.expiry_seconds);
+                }
+                ui.label("Display qty: ");
+                ui.text_edit_singleline(&mut self.display_quantity);
+                ComboBox::from_id_salt("post_only")
+                    .selected_text(match self.post_only {
+                        None => "Not post-only",
+                        Some(PostOnly::Reject) => "Post-only: reject",
+                        Some(PostOnly::Reprice) => "Post-only: reprice",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.post_only, None, "Not post-only");
+                        ui.selectable_value(
+                            &mut self.post_only,
+                            Some(PostOnly::Reject),
+                            "Post-only: reject",
+                        );
+                        ui.selectable_value(
+                            &mut self.post_only,
+                            Some(PostOnly::Reprice),
+                            "Post-only: reprice",
+                        );
+                    });
+                if ui.button("End session").clicked() {
+                    self.end_session();
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Buy").clicked() {
+                    self.enter_order(Side::Buy);
+                }
+                if ui.button("Sell").clicked() {
+                    self.enter_order(Side::Sell);
+                }
+                if ui.button("Market Buy").clicked() {
+                    self.enter_market_order(Side::Buy);
+                }
+                if ui.button("Market Sell").clicked() {
+                    self.enter_market_order(Side::Sell);
+                }
+            });
+            ui.horizontal(|ui| {
+                ComboBox::from_id_salt("peg")
+                    .selected_text(match self.peg_reference {
+                        None => "Not pegged".to_string(),
+                        Some(reference) => format!("{:?} peg", reference),
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.peg_reference, None, "Not pegged");
+                        for reference in [
+                            PegReference::Primary,
+                            PegReference::Market,
+                            PegReference::Midpoint,
+                        ] {
+                            ui.selectable_value(
+                                &mut self.peg_reference,
+                                Some(reference),
+                                format!("{:?} peg", reference),
+                            );
+                        }
+                    });
+                if self.peg_reference.is_some() {
+                    ui.label("Offset: ");
+                    ui.text_edit_singleline(&mut self.peg_offset);
+                    ui.label("Limit: ");
+                    ui.text_edit_singleline(&mut self.peg_limit);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Trigger: ");
+                ui.text_edit_singleline(&mut self.trigger);
+                if ui.button("Stop Buy").clicked() {
+                    self.enter_stop_order(Side::Buy, false);
+                }
+                if ui.button("Stop Sell").clicked() {
+                    self.enter_stop_order(Side::Sell, false);
+                }
+                if ui.button("Stop-Limit Buy").clicked() {
+                    self.enter_stop_order(Side::Buy, true);
+                }
+                if ui.button("Stop-Limit Sell").clicked() {
+                    self.enter_stop_order(Side::Sell, true);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Trail: ");
+                ui.text_edit_singleline(&mut self.trail);
+                if ui.button("Trailing Stop Buy").clicked() {
+                    self.enter_trailing_stop_order(Side::Buy);
+                }
+                if ui.button("Trailing Stop Sell").clicked() {
+                    self.enter_trailing_stop_order(Side::Sell);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Order id: ");
+                ui.text_edit_singleline(&mut self.order_id);
+                if ui.button("Amend").clicked() {
+                    self.amend_order();
+                }
+            });
+            ui.label(&self.status);
+            ui.separator();
+
+            let mut auction = None;
+            ui.horizontal(|ui| {
+                match self.exchange.book(&self.symbol) {
+                    Some(book) if book.phase == TradingPhase::Auction => {
+                        let price = uncrossing_price(book, book.last_price());
+                        ui.label(match price {
+                            Some(price) => format!("Auction: indicative price {}", price),
+                            None => "Auction: not crossed".to_string(),
+                        });
+                    }
+                    _ => {
+                        ui.label("Continuous trading");
+                    }
+                }
+                if ui.button("Start auction").clicked() {
+                    auction = Some(true);
+                }
+                if ui.button("Uncross").clicked() {
+                    auction = Some(false);
+                }
+            });
+            match auction {
+                Some(true) => self.start_auction(),
+                Some(false) => self.uncross_auction(),
+                None => {}
+            }
+
+            let mut cancelled = None;
+            let mut rebuild = false;
+            if let Some(book) = self.exchange.book(&self.symbol) {
+                Self::show_depth(ui, book);
+                ui.separator();
+                Self::show_tape(ui, book);
+                ui.separator();
+                let selected = self.bar_kind;
+                ui.horizontal(|ui| {
+                    ui.label("Bars: ");
+                    ComboBox::from_id_salt("bars")
+                        .selected_text(BAR_KINDS[self.bar_kind].0)
+                        .show_ui(ui, |ui| {
+                            for (index, (label, _)) in BAR_KINDS.iter().enumerate() {
+                                ui.selectable_value(&mut self.bar_kind, index, *label);
+                            }
+                        });
+                });
+                if self.bar_kind != selected {
+                    rebuild = true;
+                }
+                Self::show_chart(ui, self.candles.get(&self.symbol));
+                ui.separator();
+                ui.horizontal_top(|ui| {
+                    let buy = Self::show_orders(ui, "Buy orders", book.buy_orders.iter());
+                    let sell = Self::show_orders(ui, "Sell orders", book.sell_orders.iter());
+                    let stops = Self::show_orders(ui, "Stop orders", book.stop_orders());
+                    cancelled = buy.or(sell).or(stops);
+                });
+            }
+            if let Some(id) = cancelled {
+                self.cancel_order(id);
+            }
+            if rebuild {
+                self.rechart();
             }
-            ui.label(format!("Hello '{}', age {}", &self.name, self.age));
         });
     }
-}
\ No newline at end of file
+}
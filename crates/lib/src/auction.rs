@@ -0,0 +1,258 @@
+use std::collections::BTreeSet;
+
+use crate::{Execution, FulfillmentEngine, Price, Trade};
+
+/// A call auction over a `Trade`. Orders collected while the book is in
+/// `TradingPhase::Auction` are uncrossed at one equilibrium price, and every
+/// match executes at that price.
+///
+/// The price is the one that executes the most volume. Ties go to the price
+/// leaving the smallest imbalance; then, if the surplus is all on one side,
+/// to the highest price for a buy surplus or the lowest for a sell surplus;
+/// then to the price closest to the reference price; and finally to the
+/// lowest price. Only orders resting on the book take part: market, IOC and
+/// FOK orders are refused while the book is in auction, and any still
+/// waiting to match are expired before the uncross.
+pub struct AuctionEngine<'a> {
+    pub trades: &'a mut Trade,
+    reference_price: Option<Price>,
+    // Worked out on the first match so the whole uncross uses one price.
+    price: Option<Price>,
+}
+
+impl<'a> AuctionEngine<'a> {
+    /// An auction whose reference price is the book's last traded price.
+    pub fn new(trades: &'a mut Trade) -> Self {
+        let reference_price = trades.last_price();
+        Self::with_reference_price(trades, reference_price)
+    }
+
+    pub fn with_reference_price(trades: &'a mut Trade, reference_price: Option<Price>) -> Self {
+        Self {
+            trades,
+            reference_price,
+            price: None,
+        }
+    }
+
+    /// The price the book would uncross at right now, or `None` if it is not
+    /// crossed.
+    pub fn indicative_price(&self) -> Option<Price> {
+        self.price
+            .or_else(|| uncrossing_price(self.trades, self.reference_price))
+    }
+}
+
+impl<'a> FulfillmentEngine for AuctionEngine<'a> {
+    fn fulfill(&mut self) -> Option<Execution> {
+        if self.price.is_none() {
+            self.price = uncrossing_price(self.trades, self.reference_price);
+            self.trades.expire_incoming();
+        }
+        self.trades.execute_at(self.price?)
+    }
+}
+
+/// Volume bought and sold if the book uncrossed at `price`.
+fn volumes_at(trades: &Trade, price: Price) -> (u64, u64) {
+    let bought = trades
+        .buy_orders
+        .iter()
+        .filter(|order| order.price >= price)
+        .map(|order| u64::from(order.total_quantity()))
+        .sum();
+    let sold = trades
+        .sell_orders
+        .iter()
+        .filter(|order| order.price <= price)
+        .map(|order| u64::from(order.total_quantity()))
+        .sum();
+    (bought, sold)
+}
+
+/// The price `trades` would uncross at against `reference_price`, or `None`
+/// if the book is not crossed. See `AuctionEngine` for how it is chosen.
+pub fn uncrossing_price(trades: &Trade, reference_price: Option<Price>) -> Option<Price> {
+    let candidates: BTreeSet<Price> = trades
+        .buy_orders
+        .levels()
+        .chain(trades.sell_orders.levels())
+        .map(|(price, _)| price)
+        .collect();
+
+    // (price, bought, sold) at every price that executes something, lowest
+    // price first.
+    let crossed: Vec<(Price, u64, u64)> = candidates
+        .into_iter()
+        .map(|price| {
+            let (bought, sold) = volumes_at(trades, price);
+            (price, bought, sold)
+        })
+        .filter(|&(_, bought, sold)| bought.min(sold) > 0)
+        .collect();
+    let rank = |&(_, bought, sold): &(Price, u64, u64)| {
+        (bought.min(sold), std::cmp::Reverse(bought.abs_diff(sold)))
+    };
+    let best = crossed.iter().map(rank).max()?;
+    let tied: Vec<_> = crossed
+        .into_iter()
+        .filter(|candidate| rank(candidate) == best)
+        .collect();
+
+    if tied.iter().all(|&(_, bought, sold)| bought > sold) {
+        return tied.last().map(|&(price, _, _)| price);
+    }
+    if tied.iter().all(|&(_, bought, sold)| sold > bought) {
+        return tied.first().map(|&(price, _, _)| price);
+    }
+    tied.into_iter()
+        .map(|(price, _, _)| price)
+        .min_by_key(|&price| {
+            let distance = reference_price.map_or(0, |reference| price.abs_diff(reference));
+            (distance, price)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BookEvent, ExpiryReason, Order, Side, TimeInForce, TradingPhase};
+
+    fn auction_book(orders: &[(Side, i32, u32)]) -> Trade {
+        let mut trades = Trade::new();
+        trades.phase = TradingPhase::Auction;
+        for &(side, price, quantity) in orders {
+            trades.add_order(side, price, quantity).unwrap();
+        }
+        trades
+    }
+
+    #[test]
+    fn uncrosses_at_the_price_with_most_volume() {
+        let mut trades = auction_book(&[
+            (Side::Buy, 102, 10),
+            (Side::Buy, 101, 10),
+            (Side::Buy, 100, 10),
+            (Side::Sell, 99, 10),
+            (Side::Sell, 100, 10),
+            (Side::Sell, 101, 15),
+        ]);
+
+        // 100 and 101 both execute 20; 100 leaves the smaller imbalance
+        assert_eq!(uncrossing_price(&trades, None), Some(Price::from(100)));
+        let mut engine = AuctionEngine::new(&mut trades);
+        assert_eq!(engine.indicative_price(), Some(Price::from(100)));
+        let executed = engine.fulfill_all();
+
+        assert!(
+            executed
+                .iter()
+                .all(|execution| execution.price == Price::from(100))
+        );
+        let volume: u32 = executed.iter().map(|execution| execution.quantity).sum();
+        assert_eq!(volume, 20);
+        assert_eq!(trades.buy_orders.best().unwrap().price, Price::from(100));
+        assert_eq!(trades.sell_orders.best().unwrap().price, Price::from(101));
+    }
+
+    #[test]
+    fn buy_surplus_takes_the_highest_price() {
+        let mut trades = auction_book(&[(Side::Buy, 105, 20), (Side::Sell, 100, 10)]);
+        let engine = AuctionEngine::new(&mut trades);
+        assert_eq!(engine.indicative_price(), Some(Price::from(105)));
+    }
+
+    #[test]
+    fn sell_surplus_takes_the_lowest_price() {
+        let mut trades = auction_book(&[(Side::Buy, 105, 10), (Side::Sell, 100, 20)]);
+        let engine = AuctionEngine::new(&mut trades);
+        assert_eq!(engine.indicative_price(), Some(Price::from(100)));
+    }
+
+    #[test]
+    fn mixed_surplus_ties_go_to_the_reference_price() {
+        // 100 leaves a buy surplus of 5 and 101 a sell surplus of 5
+        let mut trades = auction_book(&[
+            (Side::Buy, 101, 10),
+            (Side::Buy, 100, 5),
+            (Side::Sell, 100, 10),
+            (Side::Sell, 101, 5),
+        ]);
+        let engine = AuctionEngine::with_reference_price(&mut trades, Some(Price::from(101)));
+        assert_eq!(engine.indicative_price(), Some(Price::from(101)));
+        let engine = AuctionEngine::with_reference_price(&mut trades, Some(Price::from(100)));
+        assert_eq!(engine.indicative_price(), Some(Price::from(100)));
+    }
+
+    #[test]
+    fn balanced_ties_go_to_the_reference_price() {
+        let mut trades = auction_book(&[(Side::Buy, 105, 10), (Side::Sell, 100, 10)]);
+        let engine = AuctionEngine::with_reference_price(&mut trades, Some(Price::from(103)));
+        assert_eq!(engine.indicative_price(), Some(Price::from(105)));
+        let engine = AuctionEngine::with_reference_price(&mut trades, Some(Price::from(101)));
+        assert_eq!(engine.indicative_price(), Some(Price::from(100)));
+        let engine = AuctionEngine::with_reference_price(&mut trades, None);
+        assert_eq!(engine.indicative_price(), Some(Price::from(100)));
+    }
+
+    #[test]
+    fn uncrossed_book_has_no_auction_price() {
+        let mut trades = auction_book(&[(Side::Buy, 99, 10), (Side::Sell, 100, 10)]);
+        let mut engine = AuctionEngine::new(&mut trades);
+        assert_eq!(engine.indicative_price(), None);
+        assert!(engine.fulfill_all().is_empty());
+    }
+
+    #[test]
+    fn immediate_orders_are_refused_during_the_auction() {
+        let mut trades = auction_book(&[(Side::Sell, 100, 10)]);
+        for order in [
+            Order::market(Side::Buy, 5),
+            Order::new(Side::Buy, 100, 5).with_time_in_force(TimeInForce::Ioc),
+            Order::new(Side::Buy, 100, 5).with_time_in_force(TimeInForce::Fok),
+        ] {
+            assert_eq!(
+                trades.submit(order),
+                Err("immediate orders cannot enter an auction")
+            );
+        }
+    }
+
+    #[test]
+    fn waiting_immediate_orders_expire_at_the_uncross() {
+        let mut trades = auction_book(&[]);
+        trades.phase = TradingPhase::Continuous;
+        let ioc = Order::new(Side::Buy, 100, 5).with_time_in_force(TimeInForce::Ioc);
+        let ioc = trades.submit(ioc).unwrap();
+        trades.phase = TradingPhase::Auction;
+        trades.add_order(Side::Sell, 100, 10).unwrap();
+        trades.add_order(Side::Buy, 100, 4).unwrap();
+
+        let executed = AuctionEngine::new(&mut trades).fulfill_all();
+        trades.phase = TradingPhase::Continuous;
+
+        assert_eq!(executed.len(), 1);
+        assert!(trades.take_events().iter().any(|event| matches!(
+            event,
+            BookEvent::Expired {
+                order,
+                reason: ExpiryReason::ImmediateOrCancel,
+            } if order.id == ioc
+        )));
+        assert!(trades.execute_trade().is_none());
+        assert_eq!(trades.sell_orders.best().unwrap().quantity, 6);
+    }
+
+    #[test]
+    fn hidden_iceberg_quantity_takes_part() {
+        let mut trades = auction_book(&[(Side::Buy, 100, 12)]);
+        let iceberg = Order::new(Side::Sell, 100, 12).with_display_quantity(4);
+        trades.submit(iceberg).unwrap();
+
+        let executed = AuctionEngine::new(&mut trades).fulfill_all();
+
+        let volume: u32 = executed.iter().map(|execution| execution.quantity).sum();
+        assert_eq!(volume, 12);
+        assert!(trades.sell_orders.is_empty());
+    }
+}
@@ -0,0 +1,269 @@
+use crate::{Execution, Price, Timestamp};
+
+/// How executions are grouped into bars.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BarKind {
+    /// A bar per interval of this many milliseconds, aligned to the epoch.
+    /// Intervals without trades have no bar.
+    Time(Timestamp),
+    /// A bar per this many trades.
+    Ticks(u32),
+    /// A bar per this much traded quantity. A trade that overfills a bar is
+    /// split, the rest going to the next bar.
+    Volume(u64),
+}
+
+impl BarKind {
+    pub const SECOND: Self = Self::Time(1_000);
+    pub const MINUTE: Self = Self::Time(60_000);
+    pub const FIVE_MINUTES: Self = Self::Time(300_000);
+    pub const HOUR: Self = Self::Time(3_600_000);
+    /// Calendar days in UTC.
+    pub const DAY: Self = Self::Time(86_400_000);
+}
+
+/// One OHLCV bar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Candle {
+    /// Start of the interval for a time bar; time of the first trade
+    /// otherwise.
+    pub start: Timestamp,
+    /// Time of the last trade in the bar.
+    pub end: Timestamp,
+    pub open: Price,
+    pub high: Price,
+    pub low: Price,
+    pub close: Price,
+    pub volume: u64,
+    /// Trades in the bar. A trade split across volume bars counts in each.
+    pub trade_count: u64,
+}
+
+impl Candle {
+    fn open(start: Timestamp, execution: &Execution, quantity: u64) -> Self {
+        Self {
+            start,
+            end: execution.timestamp,
+            open: execution.price,
+            high: execution.price,
+            low: execution.price,
+            close: execution.price,
+            volume: quantity,
+            trade_count: 1,
+        }
+    }
+
+    fn add(&mut self, execution: &Execution, quantity: u64) {
+        self.end = execution.timestamp;
+        self.high = self.high.max(execution.price);
+        self.low = self.low.min(execution.price);
+        self.close = execution.price;
+        self.volume += quantity;
+        self.trade_count += 1;
+    }
+}
+
+/// Bars of one kind built from executions as they are recorded: the bars
+/// already closed plus the one still forming.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CandleSeries {
+    kind: BarKind,
+    closed: Vec<Candle>,
+    current: Option<Candle>,
+}
+
+impl CandleSeries {
+    pub fn new(kind: BarKind) -> Result<Self, &'static str> {
+        let size = match kind {
+            BarKind::Time(interval) => interval,
+            BarKind::Ticks(ticks) => u64::from(ticks),
+            BarKind::Volume(volume) => volume,
+        };
+        if size == 0 {
+            return Err("bar size must be positive");
+        }
+        Ok(Self {
+            kind,
+            closed: Vec::new(),
+            current: None,
+        })
+    }
+
+    /// Builds a series from past executions, oldest first.
+    pub fn from_executions<'a>(
+        kind: BarKind,
+        executions: impl IntoIterator<Item = &'a Execution>,
+    ) -> Result<Self, &'static str> {
+        let mut series = Self::new(kind)?;
+        for execution in executions {
+            series.record(execution);
+        }
+        Ok(series)
+    }
+
+    pub fn kind(&self) -> BarKind {
+        self.kind
+    }
+
+    /// Adds an execution to the bar it falls in, closing bars as they fill.
+    /// Executions must be recorded in the order they happened.
+    pub fn record(&mut self, execution: &Execution) {
+        match self.kind {
+            BarKind::Time(interval) => {
+                let start = execution.timestamp - execution.timestamp % interval;
+                match &mut self.current {
+                    Some(candle) if candle.start == start => {
+                        candle.add(execution, u64::from(execution.quantity))
+                    }
+                    _ => self.start(start, execution, u64::from(execution.quantity)),
+                }
+            }
+            BarKind::Ticks(ticks) => {
+                self.fill(execution, u64::from(execution.quantity));
+                if self
+                    .current
+                    .is_some_and(|candle| candle.trade_count >= u64::from(ticks))
+                {
+                    self.close();
+                }
+            }
+            BarKind::Volume(size) => {
+                let mut quantity = u64::from(execution.quantity);
+                while quantity > 0 {
+                    let room = size - self.current.map_or(0, |candle| candle.volume);
+                    let part = quantity.min(room);
+                    self.fill(execution, part);
+                    quantity -= part;
+                    if part == room {
+                        self.close();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds to the forming bar, or opens one at the execution's time.
+    fn fill(&mut self, execution: &Execution, quantity: u64) {
+        match &mut self.current {
+            Some(candle) => candle.add(execution, quantity),
+            None => self.current = Some(Candle::open(execution.timestamp, execution, quantity)),
+        }
+    }
+
+    fn start(&mut self, start: Timestamp, execution: &Execution, quantity: u64) {
+        self.close();
+        self.current = Some(Candle::open(start, execution, quantity));
+    }
+
+    fn close(&mut self) {
+        self.closed.extend(self.current.take());
+    }
+
+    /// Bars that are complete, oldest first.
+    pub fn closed(&self) -> &[Candle] {
+        &self.closed
+    }
+
+    /// The bar still forming, if any trade has gone into it.
+    pub fn current(&self) -> Option<&Candle> {
+        self.current.as_ref()
+    }
+
+    /// Every bar, oldest first, ending with the one still forming.
+    pub fn history(&self) -> impl Iterator<Item = &Candle> {
+        self.closed.iter().chain(self.current.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Side;
+
+    fn print(timestamp: Timestamp, price: i32, quantity: u32) -> Execution {
+        Execution {
+            buy_order_id: 1,
+            sell_order_id: 2,
+            price: Price::from(price),
+            quantity,
+            aggressor: Side::Buy,
+            sequence: 1,
+            timestamp,
+        }
+    }
+
+    fn ohlcv(candle: &Candle) -> (i64, i64, i64, i64, u64) {
+        let whole = |price: Price| price.units() / Price::<2>::SCALE;
+        (
+            whole(candle.open),
+            whole(candle.high),
+            whole(candle.low),
+            whole(candle.close),
+            candle.volume,
+        )
+    }
+
+    #[test]
+    fn time_bars_align_to_the_interval() {
+        let prints = [
+            print(60_500, 100, 1),
+            print(61_000, 103, 2),
+            print(119_999, 99, 3),
+            print(120_000, 101, 4),
+            // nothing traded from 180_000 to 240_000
+            print(250_000, 102, 5),
+        ];
+        let series = CandleSeries::from_executions(BarKind::MINUTE, &prints).unwrap();
+
+        let bars: Vec<_> = series.history().map(ohlcv).collect();
+        assert_eq!(
+            bars,
+            vec![
+                (100, 103, 99, 99, 6),
+                (101, 101, 101, 101, 4),
+                (102, 102, 102, 102, 5)
+            ]
+        );
+        let starts: Vec<_> = series.history().map(|candle| candle.start).collect();
+        assert_eq!(starts, vec![60_000, 120_000, 240_000]);
+        assert_eq!(series.closed().len(), 2);
+        assert_eq!(series.current().unwrap().end, 250_000);
+    }
+
+    #[test]
+    fn tick_bars_close_after_each_count() {
+        let prints = [print(0, 100, 1), print(1, 101, 1), print(2, 102, 1)];
+        let series = CandleSeries::from_executions(BarKind::Ticks(2), &prints).unwrap();
+
+        assert_eq!(series.closed().len(), 1);
+        assert_eq!(ohlcv(&series.closed()[0]), (100, 101, 100, 101, 2));
+        assert_eq!(series.current().unwrap().trade_count, 1);
+    }
+
+    #[test]
+    fn volume_bars_split_large_trades() {
+        let prints = [print(0, 100, 4), print(1, 101, 13)];
+        let series = CandleSeries::from_executions(BarKind::Volume(5), &prints).unwrap();
+
+        let bars: Vec<_> = series.history().map(ohlcv).collect();
+        assert_eq!(
+            bars,
+            vec![
+                (100, 101, 100, 101, 5),
+                (101, 101, 101, 101, 5),
+                (101, 101, 101, 101, 5),
+                (101, 101, 101, 101, 2)
+            ]
+        );
+        assert_eq!(series.closed()[0].trade_count, 2);
+    }
+
+    #[test]
+    fn empty_bars_are_rejected() {
+        assert_eq!(
+            CandleSeries::new(BarKind::Volume(0)),
+            Err("bar size must be positive")
+        );
+        assert!(CandleSeries::new(BarKind::DAY).is_ok());
+    }
+}
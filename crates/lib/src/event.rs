@@ -0,0 +1,28 @@
+use crate::Order;
+
+/// Something that happened to an order other than a fill. The book collects
+/// these as they happen; callers drain them with `Trade::take_events`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BookEvent {
+    /// The order was removed because its time in force ran out, or by
+    /// self-trade prevention. `order` holds what was left of it at the time.
+    Expired { order: Order, reason: ExpiryReason },
+    /// A stop order's trigger was reached. `order` is the order as it entered
+    /// matching: converted to a market or limit order, with a new sequence.
+    Triggered { order: Order },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExpiryReason {
+    /// Remainder of an immediate-or-cancel or market order after matching.
+    ImmediateOrCancel,
+    /// Fill-or-kill order that could not be filled in full on entry.
+    FillOrKill,
+    /// Day order still resting at `Trade::end_session`.
+    EndOfDay,
+    /// Good-till-date order whose expiry time was reached.
+    GoodTillDate,
+    /// Cancelled so that it would not trade with an order from its own
+    /// account.
+    SelfTrade,
+}
@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    BookEvent, MarketDataUpdate, Order, OrderBookEngine, OrderId, Price, Timestamp, Trade,
+};
+
+/// A venue holding one order book per instrument, keyed by symbol. Orders are
+/// routed to the book for their symbol; ids are only unique within a book.
+#[derive(Clone, Debug, Default)]
+pub struct Exchange {
+    books: BTreeMap<String, Trade>,
+}
+
+impl Exchange {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an instrument with `book` as its order book.
+    pub fn list_instrument(&mut self, symbol: &str, book: Trade) -> Result<(), &'static str> {
+        if symbol.is_empty() {
+            return Err("symbol cannot be empty");
+        }
+        if self.books.contains_key(symbol) {
+            return Err("instrument already listed");
+        }
+        self.books.insert(symbol.to_string(), book);
+        Ok(())
+    }
+
+    /// Symbols of every listed instrument, in alphabetical order.
+    pub fn instruments(&self) -> impl Iterator<Item = &str> {
+        self.books.keys().map(String::as_str)
+    }
+
+    pub fn book(&self, symbol: &str) -> Option<&Trade> {
+        self.books.get(symbol)
+    }
+
+    pub fn book_mut(&mut self, symbol: &str) -> Option<&mut Trade> {
+        self.books.get_mut(symbol)
+    }
+
+    /// An engine that matches the book for `symbol`.
+    pub fn engine(&mut self, symbol: &str) -> Option<OrderBookEngine<'_>> {
+        self.book_mut(symbol).map(OrderBookEngine::new)
+    }
+
+    /// Enters an order on the book for `symbol`. See `Trade::submit`.
+    pub fn submit(&mut self, symbol: &str, order: Order) -> Result<OrderId, &'static str> {
+        self.book_mut(symbol)
+            .ok_or("unknown instrument")?
+            .submit(order)
+    }
+
+    pub fn cancel(&mut self, symbol: &str, id: OrderId) -> Option<Order> {
+        self.book_mut(symbol)?.cancel(id)
+    }
+
+    pub fn amend(
+        &mut self,
+        symbol: &str,
+        id: OrderId,
+        new_price: impl Into<Price>,
+        new_quantity: u32,
+    ) -> Result<(), &'static str> {
+        self.book_mut(symbol)
+            .ok_or("unknown instrument")?
+            .amend(id, new_price, new_quantity)
+    }
+
+    /// Sets the clock of every book.
+    pub fn set_time(&mut self, time: Timestamp) {
+        for book in self.books.values_mut() {
+            book.set_time(time);
+        }
+    }
+
+    /// Closes the trading session on every book.
+    pub fn end_session(&mut self) {
+        for book in self.books.values_mut() {
+            book.end_session();
+        }
+    }
+
+    /// Events recorded by every book since the last call, with the symbol of
+    /// the book they happened on. Books are drained in symbol order.
+    pub fn take_events(&mut self) -> Vec<(String, BookEvent)> {
+        self.books
+            .iter_mut()
+            .flat_map(|(symbol, book)| {
+                book.take_events()
+                    .into_iter()
+                    .map(|event| (symbol.clone(), event))
+            })
+            .collect()
+    }
+
+    /// Market data published by every book since the last call, with the
+    /// symbol of the book it describes. Each book numbers its own updates.
+    pub fn take_market_data(&mut self) -> Vec<(String, MarketDataUpdate)> {
+        self.books
+            .iter_mut()
+            .flat_map(|(symbol, book)| {
+                book.take_market_data()
+                    .into_iter()
+                    .map(|update| (symbol.clone(), update))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FulfillmentEngine, MatchingMode, Side, TimeInForce};
+
+    fn exchange_with(symbols: &[&str]) -> Exchange {
+        let mut exchange = Exchange::new();
+        for symbol in symbols {
+            exchange.list_instrument(symbol, Trade::new()).unwrap();
+        }
+        exchange
+    }
+
+    #[test]
+    fn instruments_are_listed_in_symbol_order() {
+        let exchange = exchange_with(&["MSFT", "AAPL", "GOOG"]);
+        let symbols: Vec<_> = exchange.instruments().collect();
+        assert_eq!(symbols, vec!["AAPL", "GOOG", "MSFT"]);
+    }
+
+    #[test]
+    fn listing_rejects_duplicates_and_empty_symbols() {
+        let mut exchange = exchange_with(&["AAPL"]);
+        assert_eq!(
+            exchange.list_instrument("AAPL", Trade::new()),
+            Err("instrument already listed")
+        );
+        assert_eq!(
+            exchange.list_instrument("", Trade::new()),
+            Err("symbol cannot be empty")
+        );
+    }
+
+    #[test]
+    fn orders_are_routed_by_symbol() {
+        let mut exchange = exchange_with(&["AAPL", "MSFT"]);
+        exchange
+            .submit("AAPL", Order::new(Side::Buy, 100, 10))
+            .unwrap();
+        exchange
+            .submit("MSFT", Order::new(Side::Sell, 90, 10))
+            .unwrap();
+
+        // the two orders would cross, but they are on different books
+        assert!(exchange.engine("AAPL").unwrap().fulfill_all().is_empty());
+        assert!(exchange.engine("MSFT").unwrap().fulfill_all().is_empty());
+        assert_eq!(exchange.book("AAPL").unwrap().buy_orders.len(), 1);
+        assert_eq!(exchange.book("MSFT").unwrap().sell_orders.len(), 1);
+    }
+
+    #[test]
+    fn unknown_instrument_is_rejected() {
+        let mut exchange = exchange_with(&["AAPL"]);
+        assert_eq!(
+            exchange.submit("TSLA", Order::new(Side::Buy, 100, 10)),
+            Err("unknown instrument")
+        );
+        assert!(exchange.engine("TSLA").is_none());
+        assert!(exchange.cancel("TSLA", 1).is_none());
+    }
+
+    #[test]
+    fn books_keep_their_own_ids_and_settings() {
+        let mut exchange = Exchange::new();
+        exchange.list_instrument("AAPL", Trade::new()).unwrap();
+        exchange
+            .list_instrument("MSFT", Trade::with_matching_mode(MatchingMode::ExactPrice))
+            .unwrap();
+
+        let aapl = exchange
+            .submit("AAPL", Order::new(Side::Buy, 100, 10))
+            .unwrap();
+        let msft = exchange
+            .submit("MSFT", Order::new(Side::Buy, 100, 10))
+            .unwrap();
+        assert_eq!((aapl, msft), (1, 1));
+        assert_eq!(
+            exchange.book("MSFT").unwrap().matching_mode,
+            MatchingMode::ExactPrice
+        );
+    }
+
+    #[test]
+    fn end_session_expires_day_orders_on_every_book() {
+        let mut exchange = exchange_with(&["AAPL", "MSFT"]);
+        for symbol in ["MSFT", "AAPL"] {
+            let day = Order::new(Side::Buy, 100, 10).with_time_in_force(TimeInForce::Day);
+            exchange.submit(symbol, day).unwrap();
+        }
+
+        exchange.end_session();
+
+        let symbols: Vec<_> = exchange
+            .take_events()
+            .into_iter()
+            .map(|(symbol, _)| symbol)
+            .collect();
+        assert_eq!(symbols, vec!["AAPL", "MSFT"]);
+    }
+}
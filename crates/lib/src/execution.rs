@@ -0,0 +1,18 @@
+use crate::{OrderId, Price, Side};
+
+/// Milliseconds since the Unix epoch, as set on a book with `Trade::set_time`.
+pub type Timestamp = u64;
+
+/// One match between a buy order and a sell order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Execution {
+    pub buy_order_id: OrderId,
+    pub sell_order_id: OrderId,
+    pub price: Price,
+    pub quantity: u32,
+    /// Side of the order that arrived last and took liquidity from the book.
+    pub aggressor: Side,
+    /// Position of this execution among all executions on the book, from 1.
+    pub sequence: u64,
+    pub timestamp: Timestamp,
+}
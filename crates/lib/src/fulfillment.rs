@@ -1,9 +1,20 @@
-use crate::Trade;
+use crate::{Execution, Trade};
 
 /// Trait that abstracts a fulfillment engine. Implementors provide the logic
 /// to match and execute trades between buy and sell orders.
+///
+/// Engines follow price-time priority: the best-priced order on each side is
+/// matched first (highest buy, lowest sell), and orders at the same price are
+/// matched in ascending `Order::sequence`, i.e. first in, first out.
 pub trait FulfillmentEngine {
-    fn fulfill(&mut self) -> Option<Trade>;
+    /// Executes at most one match.
+    fn fulfill(&mut self) -> Option<Execution>;
+
+    /// Keeps matching until the book is no longer crossed and returns every
+    /// execution in the order it happened.
+    fn fulfill_all(&mut self) -> Vec<Execution> {
+        std::iter::from_fn(|| self.fulfill()).collect()
+    }
 }
 
 /// An order book engine that implements the `FulfillmentEngine` trait.
@@ -18,69 +29,59 @@ impl<'a> OrderBookEngine<'a> {
 }
 
 impl<'a> FulfillmentEngine for OrderBookEngine<'a> {
-    fn fulfill(&mut self) -> Option<Trade> {
+    fn fulfill(&mut self) -> Option<Execution> {
         self.trades.execute_trade()
     }
 }
 
 /// Convenience wrapper that keeps the original API: callers who have separate
 /// `BuyOrders` and `SellOrders` can still call this function. It internally
-/// constructs an `OrderBookEngine` and invokes the trait method.
-pub fn fulfill_orders(trades: &mut Trade) {
+/// constructs an `OrderBookEngine`, runs it to completion and returns every
+/// execution.
+pub fn fulfill_orders(trades: &mut Trade) -> Vec<Execution> {
     let mut engine = OrderBookEngine::new(trades);
-    engine.fulfill();
+    engine.fulfill_all()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Order, OrderType, Trade};
+    use crate::{MatchingMode, Price, Side, Trade};
 
     #[test]
-    fn no_trade_when_prices_differ() {
+    fn executes_trade_when_prices_cross() {
         let mut trades = Trade::new();
 
-        trades
-            .buy_orders
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 100,
-            })
-            .unwrap();
-        trades
-            .sell_orders
-            .push(Order {
-                order_type: OrderType::Sell,
-                price: 50,
-            })
-            .unwrap();
+        trades.add_order(Side::Buy, 100, 10).unwrap();
+        trades.add_order(Side::Sell, 50, 10).unwrap();
+
+        fulfill_orders(&mut trades);
+
+        assert_eq!(trades.buy_orders.len(), 0);
+        assert_eq!(trades.sell_orders.len(), 0);
+    }
+
+    #[test]
+    fn no_trade_when_prices_differ_in_exact_price_mode() {
+        let mut trades = Trade::with_matching_mode(MatchingMode::ExactPrice);
+
+        trades.add_order(Side::Buy, 100, 10).unwrap();
+        trades.add_order(Side::Sell, 50, 10).unwrap();
 
         fulfill_orders(&mut trades);
 
         assert_eq!(trades.buy_orders.len(), 1);
         assert_eq!(trades.sell_orders.len(), 1);
-        assert_eq!(trades.buy_orders.as_slice()[0].price, 100);
-        assert_eq!(trades.sell_orders.as_slice()[0].price, 50);
+        assert_eq!(trades.buy_orders.best().unwrap().price, Price::from(100));
+        assert_eq!(trades.sell_orders.best().unwrap().price, Price::from(50));
     }
 
     #[test]
     fn executes_trade_on_equal_price() {
         let mut trades = Trade::new();
 
-        trades
-            .buy_orders
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 50,
-            })
-            .unwrap();
-        trades
-            .sell_orders
-            .push(Order {
-                order_type: OrderType::Sell,
-                price: 50,
-            })
-            .unwrap();
+        trades.add_order(Side::Buy, 50, 10).unwrap();
+        trades.add_order(Side::Sell, 50, 10).unwrap();
 
         fulfill_orders(&mut trades);
 
@@ -90,37 +91,13 @@ mod tests {
 
     #[test]
     fn removes_matching_middle_orders() {
-        let mut trades = Trade::new();
+        let mut trades = Trade::with_matching_mode(MatchingMode::ExactPrice);
+
+        trades.add_order(Side::Buy, 30, 10).unwrap();
+        trades.add_order(Side::Sell, 30, 10).unwrap();
 
-        trades
-            .buy_orders
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 30,
-            })
-            .unwrap();
-        trades
-            .sell_orders
-            .push(Order {
-                order_type: OrderType::Sell,
-                price: 30,
-            })
-            .unwrap();
-
-        trades
-            .buy_orders
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 50,
-            })
-            .unwrap();
-        trades
-            .sell_orders
-            .push(Order {
-                order_type: OrderType::Sell,
-                price: 60,
-            })
-            .unwrap();
+        trades.add_order(Side::Buy, 50, 10).unwrap();
+        trades.add_order(Side::Sell, 60, 10).unwrap();
 
         fulfill_orders(&mut trades);
 
@@ -129,7 +106,78 @@ mod tests {
 
         assert_eq!(buys.len(), 1);
         assert_eq!(sells.len(), 1);
-        assert_eq!(buys.as_slice()[0].price, 50);
-        assert_eq!(sells.as_slice()[0].price, 60);
+        assert_eq!(buys.best().unwrap().price, Price::from(50));
+        assert_eq!(sells.best().unwrap().price, Price::from(60));
+    }
+
+    #[test]
+    fn fulfill_is_fifo_within_a_price_level() {
+        let mut trades = Trade::new();
+        let first = trades.add_order(Side::Sell, 50, 10).unwrap();
+        let second = trades.add_order(Side::Sell, 50, 10).unwrap();
+        trades.add_order(Side::Buy, 50, 15).unwrap();
+
+        let mut engine = OrderBookEngine::new(&mut trades);
+        let trade = engine.fulfill().unwrap();
+        assert_eq!(trade.sell_order_id, first);
+        assert_eq!(trade.quantity, 10);
+
+        let trade = engine.fulfill().unwrap();
+        assert_eq!(trade.sell_order_id, second);
+        assert_eq!(trade.quantity, 5);
+    }
+
+    #[test]
+    fn fulfill_matches_highest_buy_first() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Buy, 48, 10).unwrap();
+        let best = trades.add_order(Side::Buy, 52, 10).unwrap();
+        trades.add_order(Side::Buy, 50, 10).unwrap();
+        trades.add_order(Side::Sell, 45, 10).unwrap();
+
+        let mut engine = OrderBookEngine::new(&mut trades);
+        let trade = engine.fulfill().unwrap();
+        assert_eq!(trade.buy_order_id, best);
+        assert_eq!(trade.price, Price::from(52));
+    }
+
+    #[test]
+    fn amended_order_goes_behind_its_new_level() {
+        let mut trades = Trade::new();
+        let amended = trades.add_order(Side::Buy, 49, 10).unwrap();
+        let waiting = trades.add_order(Side::Buy, 50, 10).unwrap();
+        trades.amend(amended, 50, 10).unwrap();
+        trades.add_order(Side::Sell, 50, 10).unwrap();
+
+        let mut engine = OrderBookEngine::new(&mut trades);
+        let trade = engine.fulfill().unwrap();
+        assert_eq!(trade.buy_order_id, waiting);
+    }
+
+    #[test]
+    fn fulfill_all_returns_every_execution_in_order() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Sell, 50, 5).unwrap();
+        trades.add_order(Side::Sell, 51, 5).unwrap();
+        trades.add_order(Side::Sell, 53, 5).unwrap();
+        trades.add_order(Side::Buy, 52, 12).unwrap();
+
+        let executed = fulfill_orders(&mut trades);
+
+        let fills: Vec<(Price, u32)> = executed.iter().map(|e| (e.price, e.quantity)).collect();
+        assert_eq!(fills, vec![(Price::from(50), 5), (Price::from(51), 5)]);
+        // 2 left on the buy at 52, below the 53 ask
+        assert_eq!(trades.buy_orders.best().unwrap().quantity, 2);
+        assert_eq!(trades.sell_orders.len(), 1);
+    }
+
+    #[test]
+    fn fulfill_all_on_uncrossed_book_is_empty() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Buy, 49, 5).unwrap();
+        trades.add_order(Side::Sell, 50, 5).unwrap();
+
+        let mut engine = OrderBookEngine::new(&mut trades);
+        assert!(engine.fulfill_all().is_empty());
     }
 }
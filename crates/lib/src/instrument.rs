@@ -0,0 +1,239 @@
+use crate::{Order, OrderKind, Price, Side};
+
+/// Trading rules for one instrument. A `Trade` checks every order against
+/// them when it is entered or amended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstrumentRules {
+    /// Prices and triggers must be a whole number of ticks. Must be positive.
+    pub tick_size: Price,
+    /// Quantities must be a whole number of lots. Must be positive.
+    pub lot_size: u32,
+    pub min_price: Option<Price>,
+    pub max_price: Option<Price>,
+    /// Bounds on an order's total quantity, hidden reserve included.
+    pub min_quantity: Option<u32>,
+    pub max_quantity: Option<u32>,
+    /// Lets prices and triggers be zero or negative, as spreads and some
+    /// commodities need.
+    pub allow_non_positive_prices: bool,
+}
+
+impl Default for InstrumentRules {
+    /// Any positive price and quantity: one-cent ticks, lots of one and no
+    /// bounds.
+    fn default() -> Self {
+        Self {
+            tick_size: Price::EPSILON,
+            lot_size: 1,
+            min_price: None,
+            max_price: None,
+            min_quantity: None,
+            max_quantity: None,
+            allow_non_positive_prices: false,
+        }
+    }
+}
+
+impl InstrumentRules {
+    /// Checks a newly entered order: its quantity, display quantity, price
+    /// and trigger. The price of a pegged order comes from the book, so it
+    /// is checked with `check_price` once known.
+    pub fn check(&self, order: &Order) -> Result<(), &'static str> {
+        self.check_quantity(
+            order
+                .checked_total_quantity()
+                .ok_or("total quantity is too large")?,
+        )?;
+        if let Some(display_quantity) = order.display_quantity
+            && !display_quantity.is_multiple_of(self.lot_size.max(1))
+        {
+            return Err("display quantity is not a multiple of the lot size");
+        }
+        match order.kind {
+            OrderKind::Stop { trigger } | OrderKind::StopLimit { trigger } => {
+                self.check_trigger(trigger)?
+            }
+            // Set from the last traded price on entry.
+            OrderKind::TrailingStop { .. } | OrderKind::Limit | OrderKind::Market => {}
+        }
+        match order.kind {
+            OrderKind::Limit | OrderKind::StopLimit { .. } if order.peg.is_none() => {
+                self.check_price(order.price)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_price(&self, price: Price) -> Result<(), &'static str> {
+        if !self.allow_non_positive_prices && price <= Price::ZERO {
+            Err("price must be positive")
+        } else if price.to_ticks(self.tick_size).is_none() {
+            Err("price is not a multiple of the tick size")
+        } else if self.min_price.is_some_and(|min| price < min) {
+            Err("price is below the minimum price")
+        } else if self.max_price.is_some_and(|max| price > max) {
+            Err("price is above the maximum price")
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Like `check_price`, but a trigger is not held to the price bounds.
+    pub fn check_trigger(&self, trigger: Price) -> Result<(), &'static str> {
+        if !self.allow_non_positive_prices && trigger <= Price::ZERO {
+            Err("trigger price must be positive")
+        } else if trigger.to_ticks(self.tick_size).is_none() {
+            Err("trigger price is not a multiple of the tick size")
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn check_quantity(&self, quantity: u32) -> Result<(), &'static str> {
+        if quantity == 0 {
+            Err("quantity must be positive")
+        } else if !quantity.is_multiple_of(self.lot_size.max(1)) {
+            Err("quantity is not a multiple of the lot size")
+        } else if self.min_quantity.is_some_and(|min| quantity < min) {
+            Err("quantity is below the minimum quantity")
+        } else if self.max_quantity.is_some_and(|max| quantity > max) {
+            Err("quantity is above the maximum quantity")
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Rounds `price` onto a tick, away from the other side of the book:
+    /// down for a buy, up for a sell. `None` if that is out of range.
+    pub fn round_to_tick(&self, side: Side, price: Price) -> Option<Price> {
+        let tick = self.tick_size.units();
+        if tick <= 0 {
+            return None;
+        }
+        let below = price.checked_sub(Price::from_units(price.units().rem_euclid(tick)))?;
+        match side {
+            Side::Buy => Some(below),
+            Side::Sell if below == price => Some(price),
+            Side::Sell => below.checked_add(self.tick_size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(s: &str) -> Price {
+        s.parse().unwrap()
+    }
+
+    fn rules() -> InstrumentRules {
+        InstrumentRules {
+            tick_size: price("0.25"),
+            lot_size: 10,
+            min_price: Some(price("50")),
+            max_price: Some(price("150")),
+            min_quantity: Some(20),
+            max_quantity: Some(1000),
+            allow_non_positive_prices: false,
+        }
+    }
+
+    #[test]
+    fn each_rule_has_its_own_rejection() {
+        let rules = rules();
+        assert_eq!(rules.check_price(price("100.25")), Ok(()));
+        assert_eq!(
+            rules.check_price(price("100.10")),
+            Err("price is not a multiple of the tick size")
+        );
+        assert_eq!(
+            rules.check_price(price("49.75")),
+            Err("price is below the minimum price")
+        );
+        assert_eq!(
+            rules.check_price(price("150.25")),
+            Err("price is above the maximum price")
+        );
+        assert_eq!(
+            rules.check_price(Price::ZERO),
+            Err("price must be positive")
+        );
+
+        assert_eq!(rules.check_quantity(30), Ok(()));
+        assert_eq!(rules.check_quantity(0), Err("quantity must be positive"));
+        assert_eq!(
+            rules.check_quantity(25),
+            Err("quantity is not a multiple of the lot size")
+        );
+        assert_eq!(
+            rules.check_quantity(10),
+            Err("quantity is below the minimum quantity")
+        );
+        assert_eq!(
+            rules.check_quantity(1010),
+            Err("quantity is above the maximum quantity")
+        );
+    }
+
+    #[test]
+    fn non_positive_prices_can_be_allowed() {
+        let rules = InstrumentRules {
+            allow_non_positive_prices: true,
+            ..InstrumentRules::default()
+        };
+        assert_eq!(rules.check_price(price("-2.50")), Ok(()));
+        assert_eq!(rules.check_price(Price::ZERO), Ok(()));
+        assert_eq!(rules.check_trigger(price("-1")), Ok(()));
+    }
+
+    #[test]
+    fn check_covers_display_quantity_and_trigger() {
+        let rules = rules();
+        let iceberg = Order::new(Side::Buy, 100, 100).with_display_quantity(15);
+        assert_eq!(
+            rules.check(&iceberg),
+            Err("display quantity is not a multiple of the lot size")
+        );
+        let stop = Order::stop(Side::Sell, price("99.90"), 20);
+        assert_eq!(
+            rules.check(&stop),
+            Err("trigger price is not a multiple of the tick size")
+        );
+        // the book sets a market order's price
+        assert_eq!(rules.check(&Order::market(Side::Buy, 20)), Ok(()));
+    }
+
+    #[test]
+    fn check_rejects_a_total_that_overflows() {
+        let mut iceberg = Order::new(Side::Buy, 100, u32::MAX);
+        iceberg.hidden_quantity = 10;
+        let iceberg = iceberg.with_display_quantity(10);
+        assert_eq!(iceberg.quantity, u32::MAX);
+        assert_eq!(
+            InstrumentRules::default().check(&iceberg),
+            Err("total quantity is too large")
+        );
+    }
+
+    #[test]
+    fn rounds_away_from_the_other_side() {
+        let rules = rules();
+        assert_eq!(
+            rules.round_to_tick(Side::Buy, price("100.30")),
+            Some(price("100.25"))
+        );
+        assert_eq!(
+            rules.round_to_tick(Side::Sell, price("100.30")),
+            Some(price("100.50"))
+        );
+        assert_eq!(
+            rules.round_to_tick(Side::Sell, price("-0.30")),
+            Some(price("-0.25"))
+        );
+        assert_eq!(
+            rules.round_to_tick(Side::Sell, price("100.50")),
+            Some(price("100.50"))
+        );
+    }
+}
@@ -0,0 +1,965 @@
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+use crate::{
+    AuctionEngine, BookEvent, Exchange, Execution, FulfillmentEngine, InstrumentRules,
+    MarketDataUpdate, Order, OrderId, OrderKind, Peg, PegReference, PostOnly, Price, Side,
+    TimeInForce, Timestamp, Trade, TradingPhase, Trail,
+};
+
+/// An inbound command to an `Exchange`, as written to the journal.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Lists an instrument with a new book under `rules`.
+    List {
+        symbol: String,
+        rules: InstrumentRules,
+    },
+    Submit {
+        symbol: String,
+        order: Order,
+    },
+    Cancel {
+        symbol: String,
+        id: OrderId,
+    },
+    Amend {
+        symbol: String,
+        id: OrderId,
+        price: Price,
+        quantity: u32,
+    },
+    /// Sets the clock of every book.
+    SetTime(Timestamp),
+    EndSession,
+    SetPhase {
+        symbol: String,
+        phase: TradingPhase,
+    },
+    /// Runs an `OrderBookEngine` on the book to completion.
+    Fulfill {
+        symbol: String,
+    },
+    /// Runs an `AuctionEngine` on the book to completion.
+    Uncross {
+        symbol: String,
+    },
+}
+
+impl Command {
+    /// Symbol of the book the command is for; `None` for commands on every
+    /// book.
+    pub fn symbol(&self) -> Option<&str> {
+        match self {
+            Command::List { symbol, .. }
+            | Command::Submit { symbol, .. }
+            | Command::Cancel { symbol, .. }
+            | Command::Amend { symbol, .. }
+            | Command::SetPhase { symbol, .. }
+            | Command::Fulfill { symbol }
+            | Command::Uncross { symbol } => Some(symbol),
+            Command::SetTime(_) | Command::EndSession => None,
+        }
+    }
+}
+
+/// What a `Command` did.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Done,
+    /// An order was entered with this id.
+    Accepted(OrderId),
+    /// An engine ran; these are its executions, in order.
+    Executed(Vec<Execution>),
+    Rejected(String),
+}
+
+/// An `Exchange` that writes every command to an append-only journal
+/// before applying it, and its outcome after. Each command gets the next
+/// sequence number, from 1.
+///
+/// The journal is plain text, one record per line: `<sequence> <command>`,
+/// then `<sequence> = <outcome>`. `replay` rebuilds the exchange from it.
+/// Books are listed with the default matching mode and self-trade
+/// prevention; only their `InstrumentRules` are journaled.
+#[derive(Debug)]
+pub struct JournaledExchange<W: Write> {
+    exchange: Exchange,
+    journal: W,
+    next_sequence: u64,
+    // Set once a write fails; the journal may then end part way through.
+    failed: bool,
+}
+
+impl<W: Write> JournaledExchange<W> {
+    /// An empty exchange journaling to `journal`.
+    pub fn new(journal: W) -> Self {
+        Self {
+            exchange: Exchange::new(),
+            journal,
+            next_sequence: 1,
+            failed: false,
+        }
+    }
+
+    /// Carries on from a replayed journal, appending to `journal`. This is
+    /// usually the replayed file cut to `Replay::journal_len` and opened for
+    /// appending. If a crash left the last command without its outcome,
+    /// the outcome it had on replay is written first.
+    pub fn resume(replay: Replay, journal: W) -> Result<Self, &'static str> {
+        let mut resumed = Self {
+            exchange: replay.exchange,
+            journal,
+            next_sequence: replay.next_sequence,
+            failed: false,
+        };
+        if let Some((sequence, outcome)) = replay.pending {
+            resumed.write(&format!("{} = {}", sequence, encode_outcome(&outcome)))?;
+        }
+        Ok(resumed)
+    }
+
+    pub fn exchange(&self) -> &Exchange {
+        &self.exchange
+    }
+
+    /// See `Exchange::take_events`.
+    pub fn take_events(&mut self) -> Vec<(String, BookEvent)> {
+        self.exchange.take_events()
+    }
+
+    /// See `Exchange::take_market_data`.
+    pub fn take_market_data(&mut self) -> Vec<(String, MarketDataUpdate)> {
+        self.exchange.take_market_data()
+    }
+
+    /// Journals and applies `command`. Fails only if the journal cannot be
+    /// written, whether before or after the command was applied. After a
+    /// failure every later command is refused, as the journal may end in a
+    /// torn record or a command without its outcome; `replay` and `resume`
+    /// recover from either.
+    pub fn execute(&mut self, command: Command) -> Result<Outcome, &'static str> {
+        if self.failed {
+            return Err("journal has failed; replay it to carry on");
+        }
+        // A symbol the journal cannot hold cannot be listed, so nothing can
+        // happen on it.
+        if let Some(symbol) = command.symbol()
+            && (symbol.is_empty() || symbol.contains(char::is_whitespace))
+        {
+            let reason = match command {
+                Command::List { .. } if symbol.is_empty() => "symbol cannot be empty",
+                Command::List { .. } => "symbol cannot contain whitespace",
+                _ => "unknown instrument",
+            };
+            return Ok(Outcome::Rejected(reason.to_string()));
+        }
+
+        let sequence = self.next_sequence;
+        self.write(&format!("{} {}", sequence, encode_command(&command)))?;
+        self.next_sequence += 1;
+        let outcome = apply(&mut self.exchange, &command);
+        self.write(&format!("{} = {}", sequence, encode_outcome(&outcome)))?;
+        Ok(outcome)
+    }
+
+    fn write(&mut self, record: &str) -> Result<(), &'static str> {
+        let written = writeln!(self.journal, "{}", record).and_then(|()| self.journal.flush());
+        self.failed |= written.is_err();
+        written.map_err(|_| "could not write journal")
+    }
+}
+
+/// An exchange rebuilt by `replay`.
+#[derive(Debug)]
+pub struct Replay {
+    pub exchange: Exchange,
+    /// Every execution in the journal with the symbol of its book, in the
+    /// order they happened.
+    pub executions: Vec<(String, Execution)>,
+    next_sequence: u64,
+    // The last command, if the journal ends before its outcome.
+    pending: Option<(u64, Outcome)>,
+    journal_len: u64,
+}
+
+impl Replay {
+    /// Bytes of the journal up to the end of its last whole record. Anything
+    /// after is a record torn by a crash, and must be cut off before the
+    /// journal is resumed.
+    pub fn journal_len(&self) -> u64 {
+        self.journal_len
+    }
+}
+
+/// Applies every command in `journal` to a new exchange, in sequence,
+/// checking that each has the outcome the journal recorded for it. What a
+/// crash can leave at the end is recovered: a last command with no outcome
+/// is applied, and a last record with no newline is ignored.
+pub fn replay(mut journal: impl BufRead) -> Result<Replay, &'static str> {
+    let mut replay = Replay {
+        exchange: Exchange::new(),
+        executions: Vec::new(),
+        next_sequence: 1,
+        // The last command applied and what it did, until the journal's
+        // outcome for it is read.
+        pending: None,
+        journal_len: 0,
+    };
+
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = journal
+            .read_line(&mut line)
+            .map_err(|_| "could not read journal")?;
+        // Every record is written with its newline, so one without is torn.
+        let Some(line) = line.strip_suffix('\n') else {
+            break;
+        };
+        replay.journal_len += read as u64;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (sequence, record) = line.split_once(' ').ok_or(MALFORMED)?;
+        let sequence: u64 = parse(sequence)?;
+
+        if let Some(recorded) = record.strip_prefix("= ") {
+            let recorded = decode_outcome(recorded)?;
+            match replay.pending.take() {
+                Some((applied, outcome)) if applied == sequence => {
+                    if outcome != recorded {
+                        return Err("replayed outcome differs from the journal");
+                    }
+                }
+                _ => return Err("journal is out of sequence"),
+            }
+        } else {
+            if replay.pending.is_some() {
+                return Err("journal is missing an outcome");
+            }
+            if sequence != replay.next_sequence {
+                return Err("journal is out of sequence");
+            }
+            let command = decode_command(record)?;
+            let outcome = apply(&mut replay.exchange, &command);
+            if let (Outcome::Executed(executions), Some(symbol)) = (&outcome, command.symbol()) {
+                replay.executions.extend(
+                    executions
+                        .iter()
+                        .map(|execution| (symbol.to_string(), execution.clone())),
+                );
+            }
+            replay.next_sequence += 1;
+            replay.pending = Some((sequence, outcome));
+        }
+    }
+    Ok(replay)
+}
+
+fn apply(exchange: &mut Exchange, command: &Command) -> Outcome {
+    let done = |result: Result<(), &'static str>| match result {
+        Ok(()) => Outcome::Done,
+        Err(reason) => Outcome::Rejected(reason.to_string()),
+    };
+    let unknown = || Outcome::Rejected("unknown instrument".to_string());
+    match command {
+        Command::List { symbol, rules } => {
+            let mut book = Trade::new();
+            book.rules = *rules;
+            done(exchange.list_instrument(symbol, book))
+        }
+        Command::Submit { symbol, order } => match exchange.submit(symbol, order.clone()) {
+            Ok(id) => Outcome::Accepted(id),
+            Err(reason) => Outcome::Rejected(reason.to_string()),
+        },
+        Command::Cancel { symbol, id } => match exchange.book(symbol) {
+            Some(_) => done(
+                exchange
+                    .cancel(symbol, *id)
+                    .map(|_| ())
+                    .ok_or("order not found"),
+            ),
+            None => unknown(),
+        },
+        Command::Amend {
+            symbol,
+            id,
+            price,
+            quantity,
+        } => done(exchange.amend(symbol, *id, *price, *quantity)),
+        Command::SetTime(time) => {
+            exchange.set_time(*time);
+            Outcome::Done
+        }
+        Command::EndSession => {
+            exchange.end_session();
+            Outcome::Done
+        }
+        Command::SetPhase { symbol, phase } => match exchange.book_mut(symbol) {
+            Some(book) => {
+                book.phase = *phase;
+                Outcome::Done
+            }
+            None => unknown(),
+        },
+        Command::Fulfill { symbol } => match exchange.engine(symbol) {
+            Some(mut engine) => Outcome::Executed(engine.fulfill_all()),
+            None => unknown(),
+        },
+        Command::Uncross { symbol } => match exchange.book_mut(symbol) {
+            Some(book) => Outcome::Executed(AuctionEngine::new(book).fulfill_all()),
+            None => unknown(),
+        },
+    }
+}
+
+const MALFORMED: &str = "journal line is malformed";
+
+fn parse<T: FromStr>(field: &str) -> Result<T, &'static str> {
+    field.parse().map_err(|_| MALFORMED)
+}
+
+/// Writes `None` as `-`.
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map_or("-".to_string(), |value| value.to_string())
+}
+
+fn parse_optional<T: FromStr>(field: &str) -> Result<Option<T>, &'static str> {
+    match field {
+        "-" => Ok(None),
+        field => parse(field).map(Some),
+    }
+}
+
+/// The whitespace-separated fields of a record, read in order.
+struct Fields<'a>(std::str::SplitWhitespace<'a>);
+
+impl<'a> Fields<'a> {
+    fn new(record: &'a str) -> Self {
+        Self(record.split_whitespace())
+    }
+
+    fn next(&mut self) -> Result<&'a str, &'static str> {
+        self.0.next().ok_or(MALFORMED)
+    }
+
+    fn parse<T: FromStr>(&mut self) -> Result<T, &'static str> {
+        parse(self.next()?)
+    }
+
+    fn optional<T: FromStr>(&mut self) -> Result<Option<T>, &'static str> {
+        parse_optional(self.next()?)
+    }
+
+    fn end(mut self) -> Result<(), &'static str> {
+        match self.0.next() {
+            Some(_) => Err(MALFORMED),
+            None => Ok(()),
+        }
+    }
+}
+
+fn encode_command(command: &Command) -> String {
+    match command {
+        Command::List { symbol, rules } => format!(
+            "list {} {} {} {} {} {} {} {}",
+            symbol,
+            rules.tick_size,
+            rules.lot_size,
+            optional(rules.min_price),
+            optional(rules.max_price),
+            optional(rules.min_quantity),
+            optional(rules.max_quantity),
+            rules.allow_non_positive_prices
+        ),
+        Command::Submit { symbol, order } => format!("submit {} {}", symbol, encode_order(order)),
+        Command::Cancel { symbol, id } => format!("cancel {} {}", symbol, id),
+        Command::Amend {
+            symbol,
+            id,
+            price,
+            quantity,
+        } => format!("amend {} {} {} {}", symbol, id, price, quantity),
+        Command::SetTime(time) => format!("time {}", time),
+        Command::EndSession => "end-session".to_string(),
+        Command::SetPhase { symbol, phase } => {
+            let phase = match phase {
+                TradingPhase::Continuous => "continuous",
+                TradingPhase::Auction => "auction",
+            };
+            format!("phase {} {}", symbol, phase)
+        }
+        Command::Fulfill { symbol } => format!("fulfill {}", symbol),
+        Command::Uncross { symbol } => format!("uncross {}", symbol),
+    }
+}
+
+fn decode_command(record: &str) -> Result<Command, &'static str> {
+    let mut fields = Fields::new(record);
+    let name = fields.next()?;
+    if let Some(command) = match name {
+        "time" => Some(Command::SetTime(fields.parse()?)),
+        "end-session" => Some(Command::EndSession),
+        _ => None,
+    } {
+        fields.end()?;
+        return Ok(command);
+    }
+
+    let symbol = fields.next()?.to_string();
+    let command = match name {
+        "list" => Command::List {
+            symbol,
+            rules: InstrumentRules {
+                tick_size: fields.parse()?,
+                lot_size: fields.parse()?,
+                min_price: fields.optional()?,
+                max_price: fields.optional()?,
+                min_quantity: fields.optional()?,
+                max_quantity: fields.optional()?,
+                allow_non_positive_prices: fields.parse()?,
+            },
+        },
+        "submit" => Command::Submit {
+            symbol,
+            order: decode_order(&mut fields)?,
+        },
+        "cancel" => Command::Cancel {
+            symbol,
+            id: fields.parse()?,
+        },
+        "amend" => Command::Amend {
+            symbol,
+            id: fields.parse()?,
+            price: fields.parse()?,
+            quantity: fields.parse()?,
+        },
+        "phase" => Command::SetPhase {
+            symbol,
+            phase: match fields.next()? {
+                "continuous" => TradingPhase::Continuous,
+                "auction" => TradingPhase::Auction,
+                _ => return Err(MALFORMED),
+            },
+        },
+        "fulfill" => Command::Fulfill { symbol },
+        "uncross" => Command::Uncross { symbol },
+        _ => return Err(MALFORMED),
+    };
+    fields.end()?;
+    Ok(command)
+}
+
+/// Every field of the order, so that replay enters exactly what was
+/// submitted.
+fn encode_order(order: &Order) -> String {
+    format!(
+        "{} {} {} {} {} {} {} {} {} {} {} {} {}",
+        order.id,
+        optional(order.account),
+        encode_side(order.side),
+        encode_kind(order.kind),
+        order.price,
+        encode_time_in_force(order.time_in_force),
+        order.peg.map_or("-".to_string(), encode_peg),
+        match order.post_only {
+            None => "-",
+            Some(PostOnly::Reject) => "reject",
+            Some(PostOnly::Reprice) => "reprice",
+        },
+        order.quantity,
+        order.hidden_quantity,
+        optional(order.display_quantity),
+        order.sequence,
+        order.entry_sequence
+    )
+}
+
+fn decode_order(fields: &mut Fields) -> Result<Order, &'static str> {
+    Ok(Order {
+        id: fields.parse()?,
+        account: fields.optional()?,
+        side: decode_side(fields.next()?)?,
+        kind: decode_kind(fields.next()?)?,
+        price: fields.parse()?,
+        time_in_force: decode_time_in_force(fields.next()?)?,
+        peg: match fields.next()? {
+            "-" => None,
+            peg => Some(decode_peg(peg)?),
+        },
+        post_only: match fields.next()? {
+            "-" => None,
+            "reject" => Some(PostOnly::Reject),
+            "reprice" => Some(PostOnly::Reprice),
+            _ => return Err(MALFORMED),
+        },
+        quantity: fields.parse()?,
+        hidden_quantity: fields.parse()?,
+        display_quantity: fields.optional()?,
+        sequence: fields.parse()?,
+        entry_sequence: fields.parse()?,
+    })
+}
+
+fn encode_side(side: Side) -> &'static str {
+    match side {
+        Side::Buy => "buy",
+        Side::Sell => "sell",
+    }
+}
+
+fn decode_side(field: &str) -> Result<Side, &'static str> {
+    match field {
+        "buy" => Ok(Side::Buy),
+        "sell" => Ok(Side::Sell),
+        _ => Err(MALFORMED),
+    }
+}
+
+fn encode_kind(kind: OrderKind) -> String {
+    match kind {
+        OrderKind::Limit => "limit".to_string(),
+        OrderKind::Market => "market".to_string(),
+        OrderKind::Stop { trigger } => format!("stop:{}", trigger),
+        OrderKind::StopLimit { trigger } => format!("stop-limit:{}", trigger),
+        OrderKind::TrailingStop { trail, trigger } => {
+            let trail = match trail {
+                Trail::Amount(amount) => amount.to_string(),
+                Trail::BasisPoints(basis_points) => format!("{}bp", basis_points),
+            };
+            format!("trailing:{}:{}", trail, trigger)
+        }
+    }
+}
+
+fn decode_kind(field: &str) -> Result<OrderKind, &'static str> {
+    let mut parts = field.split(':');
+    let kind = match (parts.next(), parts.next(), parts.next()) {
+        (Some("limit"), None, None) => OrderKind::Limit,
+        (Some("market"), None, None) => OrderKind::Market,
+        (Some("stop"), Some(trigger), None) => OrderKind::Stop {
+            trigger: parse(trigger)?,
+        },
+        (Some("stop-limit"), Some(trigger), None) => OrderKind::StopLimit {
+            trigger: parse(trigger)?,
+        },
+        (Some("trailing"), Some(trail), Some(trigger)) => OrderKind::TrailingStop {
+            trail: parse(trail)?,
+            trigger: parse(trigger)?,
+        },
+        _ => return Err(MALFORMED),
+    };
+    match parts.next() {
+        Some(_) => Err(MALFORMED),
+        None => Ok(kind),
+    }
+}
+
+fn encode_time_in_force(time_in_force: TimeInForce) -> String {
+    match time_in_force {
+        TimeInForce::Gtc => "gtc".to_string(),
+        TimeInForce::Ioc => "ioc".to_string(),
+        TimeInForce::Fok => "fok".to_string(),
+        TimeInForce::Day => "day".to_string(),
+        TimeInForce::Gtd(expiry) => format!("gtd:{}", expiry),
+    }
+}
+
+fn decode_time_in_force(field: &str) -> Result<TimeInForce, &'static str> {
+    match field.split_once(':') {
+        Some(("gtd", expiry)) => Ok(TimeInForce::Gtd(parse(expiry)?)),
+        Some(_) => Err(MALFORMED),
+        None => match field {
+            "gtc" => Ok(TimeInForce::Gtc),
+            "ioc" => Ok(TimeInForce::Ioc),
+            "fok" => Ok(TimeInForce::Fok),
+            "day" => Ok(TimeInForce::Day),
+            _ => Err(MALFORMED),
+        },
+    }
+}
+
+fn encode_peg(peg: Peg) -> String {
+    let reference = match peg.reference {
+        PegReference::Primary => "primary",
+        PegReference::Market => "market",
+        PegReference::Midpoint => "midpoint",
+    };
+    format!("{}:{}:{}", reference, peg.offset, optional(peg.limit))
+}
+
+fn decode_peg(field: &str) -> Result<Peg, &'static str> {
+    let mut parts = field.split(':');
+    let (Some(reference), Some(offset), Some(limit), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(MALFORMED);
+    };
+    Ok(Peg {
+        reference: match reference {
+            "primary" => PegReference::Primary,
+            "market" => PegReference::Market,
+            "midpoint" => PegReference::Midpoint,
+            _ => return Err(MALFORMED),
+        },
+        offset: parse(offset)?,
+        limit: parse_optional(limit)?,
+    })
+}
+
+fn encode_outcome(outcome: &Outcome) -> String {
+    match outcome {
+        Outcome::Done => "done".to_string(),
+        Outcome::Accepted(id) => format!("accepted {}", id),
+        Outcome::Executed(executions) => {
+            let mut record = "executed".to_string();
+            for execution in executions {
+                record.push(' ');
+                record.push_str(&encode_execution(execution));
+            }
+            record
+        }
+        Outcome::Rejected(reason) => format!("rejected {}", reason),
+    }
+}
+
+fn decode_outcome(record: &str) -> Result<Outcome, &'static str> {
+    // The reason is free text, so it is taken as is.
+    if let Some(reason) = record.strip_prefix("rejected ") {
+        return Ok(Outcome::Rejected(reason.to_string()));
+    }
+    let mut fields = Fields::new(record);
+    let outcome = match fields.next()? {
+        "done" => Outcome::Done,
+        "accepted" => Outcome::Accepted(fields.parse()?),
+        "executed" => {
+            return fields
+                .0
+                .map(decode_execution)
+                .collect::<Result<_, _>>()
+                .map(Outcome::Executed);
+        }
+        _ => return Err(MALFORMED),
+    };
+    fields.end()?;
+    Ok(outcome)
+}
+
+fn encode_execution(execution: &Execution) -> String {
+    format!(
+        "{}:{}:{}:{}:{}:{}:{}",
+        execution.buy_order_id,
+        execution.sell_order_id,
+        execution.price,
+        execution.quantity,
+        encode_side(execution.aggressor),
+        execution.sequence,
+        execution.timestamp
+    )
+}
+
+fn decode_execution(field: &str) -> Result<Execution, &'static str> {
+    let parts: Vec<&str> = field.split(':').collect();
+    let [buy, sell, price, quantity, aggressor, sequence, timestamp] = parts[..] else {
+        return Err(MALFORMED);
+    };
+    Ok(Execution {
+        buy_order_id: parse(buy)?,
+        sell_order_id: parse(sell)?,
+        price: parse(price)?,
+        quantity: parse(quantity)?,
+        aggressor: decode_side(aggressor)?,
+        sequence: parse(sequence)?,
+        timestamp: parse(timestamp)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol() -> String {
+        "ABC".to_string()
+    }
+
+    fn submit(order: Order) -> Command {
+        Command::Submit {
+            symbol: symbol(),
+            order,
+        }
+    }
+
+    /// A session using every kind of command and order.
+    fn session() -> Vec<Command> {
+        let price = |s: &str| -> Price { s.parse().unwrap() };
+        vec![
+            Command::List {
+                symbol: symbol(),
+                rules: InstrumentRules {
+                    max_price: Some(Price::from(1000)),
+                    min_quantity: Some(1),
+                    ..InstrumentRules::default()
+                },
+            },
+            Command::SetTime(1_000),
+            submit(Order::new(Side::Sell, price("100.25"), 10).with_account(7)),
+            submit(
+                Order::new(Side::Sell, 101, 30)
+                    .with_display_quantity(10)
+                    .with_time_in_force(TimeInForce::Day),
+            ),
+            submit(Order::new(Side::Buy, 99, 5).with_time_in_force(TimeInForce::Gtd(5_000))),
+            submit(Order::new(Side::Buy, 0, 5)),
+            submit(Order::stop(Side::Buy, 101, 5)),
+            submit(Order::stop_limit(Side::Sell, 95, 94, 5)),
+            submit(Order::trailing_stop(Side::Sell, Trail::BasisPoints(150), 5)),
+            submit(Order::trailing_stop(
+                Side::Sell,
+                Trail::Amount(price("0.5")),
+                5,
+            )),
+            submit(Order::pegged(
+                Side::Buy,
+                Peg {
+                    reference: PegReference::Midpoint,
+                    offset: price("-0.05"),
+                    limit: Some(Price::from(100)),
+                },
+                5,
+            )),
+            submit(Order::new(Side::Buy, 100, 5).with_post_only(PostOnly::Reprice)),
+            submit(Order::market(Side::Buy, 25)),
+            Command::Fulfill { symbol: symbol() },
+            Command::Amend {
+                symbol: symbol(),
+                id: 3,
+                price: Price::from(98),
+                quantity: 5,
+            },
+            Command::Cancel {
+                symbol: symbol(),
+                id: 99,
+            },
+            Command::SetPhase {
+                symbol: symbol(),
+                phase: TradingPhase::Auction,
+            },
+            submit(Order::new(Side::Buy, 102, 4)),
+            Command::Uncross { symbol: symbol() },
+            Command::SetPhase {
+                symbol: symbol(),
+                phase: TradingPhase::Continuous,
+            },
+            Command::SetTime(6_000),
+            Command::EndSession,
+            Command::Fulfill {
+                symbol: "XYZ".to_string(),
+            },
+        ]
+    }
+
+    fn journaled() -> JournaledExchange<Vec<u8>> {
+        let mut exchange = JournaledExchange::new(Vec::new());
+        for command in session() {
+            exchange.execute(command).unwrap();
+        }
+        exchange
+    }
+
+    #[test]
+    fn replay_rebuilds_the_same_exchange() {
+        let live = journaled();
+        let replayed = replay(&live.journal[..]).unwrap();
+
+        assert_eq!(
+            format!("{:?}", replayed.exchange),
+            format!("{:?}", live.exchange)
+        );
+        assert!(!replayed.executions.is_empty());
+        assert_eq!(
+            replayed.executions.len() as u64,
+            live.exchange
+                .book("ABC")
+                .unwrap()
+                .tape()
+                .stats()
+                .trade_count
+        );
+    }
+
+    #[test]
+    fn every_command_round_trips() {
+        for command in session() {
+            assert_eq!(decode_command(&encode_command(&command)), Ok(command));
+        }
+    }
+
+    #[test]
+    fn records_are_sequenced_command_then_outcome() {
+        let live = journaled();
+        let journal = String::from_utf8(live.journal).unwrap();
+        let lines: Vec<&str> = journal.lines().collect();
+
+        assert_eq!(lines.len(), 2 * session().len());
+        assert!(lines[0].starts_with("1 list ABC 0.01 1 - 1000.00 1 - false"));
+        assert_eq!(lines[1], "1 = done");
+        assert_eq!(lines[11], "6 = rejected price must be positive");
+        assert_eq!(lines.last(), Some(&"23 = rejected unknown instrument"));
+    }
+
+    #[test]
+    fn resumed_exchange_continues_the_sequence() {
+        let live = journaled();
+        let replayed = replay(&live.journal[..]).unwrap();
+        let mut resumed = JournaledExchange::resume(replayed, Vec::new()).unwrap();
+
+        resumed.execute(Command::EndSession).unwrap();
+
+        assert_eq!(resumed.journal, b"24 end-session\n24 = done\n");
+    }
+
+    #[test]
+    fn tampered_journals_are_rejected() {
+        let journal = String::from_utf8(journaled().journal).unwrap();
+
+        let changed = journal.replace("6 = rejected price must be positive", "6 = accepted 6");
+        assert_eq!(
+            replay(changed.as_bytes()).err(),
+            Some("replayed outcome differs from the journal")
+        );
+        let skipped: String = journal
+            .lines()
+            .filter(|line| !line.starts_with("2 "))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        assert_eq!(
+            replay(skipped.as_bytes()).err(),
+            Some("journal is out of sequence")
+        );
+        assert_eq!(
+            replay("1 list\n".as_bytes()).err(),
+            Some("journal line is malformed")
+        );
+    }
+
+    #[test]
+    fn missing_outcomes_are_rejected_before_the_last_command() {
+        let journal = String::from_utf8(journaled().journal).unwrap();
+        let dropped: String = journal
+            .lines()
+            .filter(|line| *line != "6 = rejected price must be positive")
+            .map(|line| format!("{}\n", line))
+            .collect();
+        assert_eq!(
+            replay(dropped.as_bytes()).err(),
+            Some("journal is missing an outcome")
+        );
+    }
+
+    #[test]
+    fn crash_before_the_outcome_is_recovered() {
+        let journal = String::from_utf8(journaled().journal).unwrap();
+        let (truncated, outcome) = journal.trim_end().rsplit_once('\n').unwrap();
+        let truncated = format!("{}\n", truncated);
+
+        let replayed = replay(truncated.as_bytes()).unwrap();
+        let mut resumed = JournaledExchange::resume(replayed, truncated.into_bytes()).unwrap();
+        assert!(
+            resumed
+                .journal
+                .ends_with(format!("{}\n", outcome).as_bytes())
+        );
+        resumed.execute(Command::EndSession).unwrap();
+
+        let replayed = replay(&resumed.journal[..]).unwrap();
+        assert_eq!(
+            format!("{:?}", replayed.exchange),
+            format!("{:?}", resumed.exchange)
+        );
+        assert_eq!(replayed.next_sequence, 25);
+    }
+
+    /// Accepts `limit` bytes, then fails every write.
+    struct FailingJournal {
+        written: Vec<u8>,
+        limit: usize,
+    }
+
+    impl Write for FailingJournal {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.written.len() + buf.len() > self.limit {
+                return Err(std::io::ErrorKind::StorageFull.into());
+            }
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failed_outcome_write_refuses_later_commands() {
+        let journal = FailingJournal {
+            written: Vec::new(),
+            limit: "1 end-session\n".len(),
+        };
+        let mut exchange = JournaledExchange::new(journal);
+
+        assert_eq!(
+            exchange.execute(Command::EndSession),
+            Err("could not write journal")
+        );
+        assert_eq!(
+            exchange.execute(Command::SetTime(1)),
+            Err("journal has failed; replay it to carry on")
+        );
+        let replayed = replay(&exchange.journal.written[..]).unwrap();
+        let resumed = JournaledExchange::resume(replayed, Vec::new()).unwrap();
+        assert_eq!(resumed.journal, b"1 = done\n");
+    }
+
+    #[test]
+    fn torn_last_record_is_cut_off() {
+        let journal = String::from_utf8(journaled().journal).unwrap();
+        let whole = journal.trim_end().rsplit_once('\n').unwrap().0.len() + 1;
+        // part of the last outcome, then part of a command
+        for torn in [
+            &journal[..journal.len() - 4],
+            &format!("{}24 end-se", journal),
+        ] {
+            let replayed = replay(torn.as_bytes()).unwrap();
+            let mut resumed_journal = torn.as_bytes().to_vec();
+            resumed_journal.truncate(replayed.journal_len() as usize);
+            let mut resumed = JournaledExchange::resume(replayed, resumed_journal).unwrap();
+            resumed.execute(Command::EndSession).unwrap();
+
+            assert!(replay(&resumed.journal[..]).is_ok());
+        }
+        assert_eq!(
+            replay(&journal.as_bytes()[..journal.len() - 1])
+                .unwrap()
+                .journal_len(),
+            whole as u64
+        );
+    }
+
+    #[test]
+    fn unjournalable_symbols_are_rejected_up_front() {
+        let mut exchange = JournaledExchange::new(Vec::new());
+        let outcome = exchange
+            .execute(Command::List {
+                symbol: "A B".to_string(),
+                rules: InstrumentRules::default(),
+            })
+            .unwrap();
+
+        assert_eq!(
+            outcome,
+            Outcome::Rejected("symbol cannot contain whitespace".to_string())
+        );
+        assert!(exchange.journal.is_empty());
+    }
+}
@@ -1,14 +1,77 @@
+mod auction;
+pub use auction::AuctionEngine;
+pub use auction::uncrossing_price;
+
+mod candles;
+pub use candles::BarKind;
+pub use candles::Candle;
+pub use candles::CandleSeries;
+
+mod event;
+pub use event::BookEvent;
+pub use event::ExpiryReason;
+
+mod exchange;
+pub use exchange::Exchange;
+
+mod execution;
+pub use execution::Execution;
+pub use execution::Timestamp;
+
 mod fulfillment;
 pub use fulfillment::FulfillmentEngine;
 pub use fulfillment::OrderBookEngine;
 pub use fulfillment::fulfill_orders;
 
+mod instrument;
+pub use instrument::InstrumentRules;
+
+mod journal;
+pub use journal::Command;
+pub use journal::JournaledExchange;
+pub use journal::Outcome;
+pub use journal::Replay;
+pub use journal::replay;
+
+mod market_data;
+pub use market_data::BookChange;
+pub use market_data::DepthBook;
+pub use market_data::Level1;
+pub use market_data::Level2;
+pub use market_data::Level3;
+pub use market_data::MarketDataUpdate;
+pub use market_data::OrderEntry;
+pub use market_data::PriceLevel;
+
 mod order;
+pub use order::AccountId;
 pub use order::Order;
-pub use order::OrderType;
+pub use order::OrderId;
+pub use order::OrderKind;
+pub use order::Peg;
+pub use order::PegReference;
+pub use order::PostOnly;
+pub use order::Side;
+pub use order::TimeInForce;
+pub use order::Trail;
 
 mod order_vec;
 pub use order_vec::OrdersVec;
 
+mod price;
+pub use price::Price;
+
+mod pro_rata;
+pub use pro_rata::ProRataConfig;
+pub use pro_rata::ProRataEngine;
+pub use pro_rata::Rounding;
+
+mod tape;
+pub use tape::SessionStats;
+pub use tape::Tape;
+
 mod trade;
+pub use trade::MatchingMode;
+pub use trade::SelfTradePrevention;
 pub use trade::Trade;
+pub use trade::TradingPhase;
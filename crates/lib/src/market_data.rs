@@ -0,0 +1,334 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{Order, OrderId, Price, Side, Timestamp};
+
+/// Visible quantity resting at one price on one side of the book. Iceberg
+/// reserves are not included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PriceLevel {
+    pub price: Price,
+    pub quantity: u64,
+    pub order_count: usize,
+}
+
+impl PriceLevel {
+    pub(crate) fn new(price: Price, orders: &VecDeque<Order>) -> Self {
+        Self {
+            price,
+            quantity: orders.iter().map(|order| u64::from(order.quantity)).sum(),
+            order_count: orders.len(),
+        }
+    }
+}
+
+/// Level 1: the best bid and offer with their sizes. See `Trade::level1`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Level1 {
+    pub bid: Option<PriceLevel>,
+    pub offer: Option<PriceLevel>,
+}
+
+impl Level1 {
+    /// Best offer less best bid, if both sides have orders.
+    pub fn spread(&self) -> Option<Price> {
+        self.offer?.price.checked_sub(self.bid?.price)
+    }
+}
+
+/// Level 2: depth aggregated by price, best level first on each side. See
+/// `Trade::level2`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Level2 {
+    pub bids: Vec<PriceLevel>,
+    pub offers: Vec<PriceLevel>,
+}
+
+/// One resting order as shown in a level 3 view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OrderEntry {
+    pub id: OrderId,
+    pub side: Side,
+    pub price: Price,
+    /// Visible quantity only.
+    pub quantity: u32,
+    pub sequence: u64,
+}
+
+impl From<&Order> for OrderEntry {
+    fn from(order: &Order) -> Self {
+        Self {
+            id: order.id,
+            side: order.side,
+            price: order.price,
+            quantity: order.quantity,
+            sequence: order.sequence,
+        }
+    }
+}
+
+/// Level 3: every resting order in price-time priority, best first on each
+/// side. See `Trade::level3`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Level3 {
+    pub bids: Vec<OrderEntry>,
+    pub offers: Vec<OrderEntry>,
+}
+
+/// One incremental change to the public view of a book. See
+/// `Trade::take_market_data`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BookChange {
+    /// Orders now rest at a price that had none.
+    LevelAdded { side: Side, level: PriceLevel },
+    /// The visible quantity or order count at a price changed.
+    LevelChanged { side: Side, level: PriceLevel },
+    /// The last order at a price left the book.
+    LevelRemoved { side: Side, price: Price },
+    /// An execution. The level changes it caused come before it.
+    TradePrinted {
+        price: Price,
+        quantity: u32,
+        aggressor: Side,
+        timestamp: Timestamp,
+    },
+}
+
+/// A `BookChange` stamped with its position in the book's update stream,
+/// from 1 with no gaps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MarketDataUpdate {
+    pub sequence: u64,
+    pub change: BookChange,
+}
+
+/// A level 2 book rebuilt from a stream of `MarketDataUpdate`s, as a
+/// downstream consumer would keep it. Applied from the first update, it
+/// matches the `Trade::level2` of the book that published them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DepthBook {
+    bids: BTreeMap<Price, PriceLevel>,
+    offers: BTreeMap<Price, PriceLevel>,
+    sequence: u64,
+    last_price: Option<Price>,
+}
+
+impl DepthBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies the next update. Updates must arrive in sequence; one that
+    /// does not, or that contradicts the book, is rejected and leaves the
+    /// book as it was.
+    pub fn apply(&mut self, update: &MarketDataUpdate) -> Result<(), &'static str> {
+        if update.sequence != self.sequence + 1 {
+            return Err("market data update is out of sequence");
+        }
+        match update.change {
+            BookChange::LevelAdded { side, level } => {
+                let levels = self.side_mut(side);
+                if levels.contains_key(&level.price) {
+                    return Err("added level already exists");
+                }
+                levels.insert(level.price, level);
+            }
+            BookChange::LevelChanged { side, level } => {
+                let existing = self
+                    .side_mut(side)
+                    .get_mut(&level.price)
+                    .ok_or("changed level does not exist")?;
+                *existing = level;
+            }
+            BookChange::LevelRemoved { side, price } => {
+                self.side_mut(side)
+                    .remove(&price)
+                    .ok_or("removed level does not exist")?;
+            }
+            BookChange::TradePrinted { price, .. } => self.last_price = Some(price),
+        }
+        self.sequence = update.sequence;
+        Ok(())
+    }
+
+    /// Sequence of the last update applied; zero before the first.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Price of the last trade printed, if any.
+    pub fn last_price(&self) -> Option<Price> {
+        self.last_price
+    }
+
+    pub fn level1(&self) -> Level1 {
+        Level1 {
+            bid: self.bids.values().next_back().copied(),
+            offer: self.offers.values().next().copied(),
+        }
+    }
+
+    /// The best `depth` levels on each side, best first.
+    pub fn level2(&self, depth: usize) -> Level2 {
+        Level2 {
+            bids: self.bids.values().rev().take(depth).copied().collect(),
+            offers: self.offers.values().take(depth).copied().collect(),
+        }
+    }
+
+    fn side_mut(&mut self, side: Side) -> &mut BTreeMap<Price, PriceLevel> {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.offers,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Trade;
+
+    fn book() -> Trade {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Buy, 99, 10).unwrap();
+        trades.add_order(Side::Buy, 100, 5).unwrap();
+        trades.add_order(Side::Buy, 100, 7).unwrap();
+        trades
+            .submit(Order::new(Side::Sell, 102, 50).with_display_quantity(4))
+            .unwrap();
+        trades.add_order(Side::Sell, 103, 1).unwrap();
+        trades.add_order(Side::Sell, 104, 1).unwrap();
+        trades
+    }
+
+    #[test]
+    fn level1_shows_the_visible_top_of_book() {
+        let level1 = book().level1();
+        assert_eq!(
+            level1.bid,
+            Some(PriceLevel {
+                price: Price::from(100),
+                quantity: 12,
+                order_count: 2,
+            })
+        );
+        // only the iceberg's displayed slice
+        assert_eq!(level1.offer.unwrap().quantity, 4);
+        assert_eq!(level1.spread(), Some(Price::from(2)));
+        assert_eq!(Trade::new().level1().spread(), None);
+    }
+
+    #[test]
+    fn level2_is_cut_to_the_requested_depth() {
+        let level2 = book().level2(2);
+        let prices = |levels: &[PriceLevel]| -> Vec<Price> {
+            levels.iter().map(|level| level.price).collect()
+        };
+        assert_eq!(
+            prices(&level2.bids),
+            vec![Price::from(100), Price::from(99)]
+        );
+        assert_eq!(
+            prices(&level2.offers),
+            vec![Price::from(102), Price::from(103)]
+        );
+    }
+
+    #[test]
+    fn level3_lists_orders_in_priority() {
+        let level3 = book().level3();
+        let quantities: Vec<u32> = level3.bids.iter().map(|order| order.quantity).collect();
+        assert_eq!(quantities, vec![5, 7, 10]);
+        assert!(level3.bids[0].sequence < level3.bids[1].sequence);
+        assert_eq!(level3.offers.len(), 3);
+        assert_eq!(level3.offers[0].quantity, 4);
+    }
+
+    /// Applies everything `trades` published and checks the rebuilt book
+    /// matches it.
+    fn follow(depth: &mut DepthBook, trades: &mut Trade) {
+        for update in trades.take_market_data() {
+            depth.apply(&update).unwrap();
+        }
+        assert_eq!(depth.level2(usize::MAX), trades.level2(usize::MAX));
+    }
+
+    #[test]
+    fn depth_book_rebuilds_level2_from_updates() {
+        let mut trades = Trade::new();
+        let mut depth = DepthBook::new();
+
+        let bid = trades.add_order(Side::Buy, 100, 10).unwrap();
+        trades.add_order(Side::Buy, 100, 5).unwrap();
+        trades
+            .submit(Order::new(Side::Sell, 102, 30).with_display_quantity(10))
+            .unwrap();
+        follow(&mut depth, &mut trades);
+
+        trades.amend(bid, 100, 4).unwrap();
+        follow(&mut depth, &mut trades);
+        trades.amend(bid, 101, 4).unwrap();
+        follow(&mut depth, &mut trades);
+
+        // sweeps an iceberg slice, which refills at the same price
+        trades.submit(Order::market(Side::Buy, 12)).unwrap();
+        trades.execute_trade();
+        follow(&mut depth, &mut trades);
+        trades.execute_trade();
+        follow(&mut depth, &mut trades);
+        assert_eq!(depth.last_price(), Some(Price::from(102)));
+
+        trades.cancel(bid).unwrap();
+        follow(&mut depth, &mut trades);
+        assert_eq!(depth.level1(), trades.level1());
+    }
+
+    #[test]
+    fn updates_are_sequenced_and_prints_follow_their_levels() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Sell, 100, 10).unwrap();
+        trades.add_order(Side::Buy, 100, 10).unwrap();
+        trades.execute_trade().unwrap();
+
+        let updates = trades.take_market_data();
+        let sequences: Vec<u64> = updates.iter().map(|update| update.sequence).collect();
+        assert_eq!(sequences, vec![1, 2, 3, 4, 5]);
+        assert!(matches!(
+            updates[2].change,
+            BookChange::LevelRemoved {
+                side: Side::Buy,
+                ..
+            }
+        ));
+        assert!(matches!(
+            updates[4].change,
+            BookChange::TradePrinted {
+                quantity: 10,
+                aggressor: Side::Buy,
+                ..
+            }
+        ));
+        assert!(trades.take_market_data().is_empty());
+    }
+
+    #[test]
+    fn gaps_and_contradictions_are_rejected() {
+        let mut depth = DepthBook::new();
+        let removed = |sequence| MarketDataUpdate {
+            sequence,
+            change: BookChange::LevelRemoved {
+                side: Side::Buy,
+                price: Price::from(100),
+            },
+        };
+        assert_eq!(
+            depth.apply(&removed(2)),
+            Err("market data update is out of sequence")
+        );
+        assert_eq!(
+            depth.apply(&removed(1)),
+            Err("removed level does not exist")
+        );
+        assert_eq!(depth.sequence(), 0);
+    }
+}
@@ -1,11 +1,351 @@
-#[derive(Debug, PartialEq, Clone)]
-pub enum OrderType {
+use crate::{Price, Timestamp};
+
+/// Identifier assigned to an order when it enters a book. It stays the same for
+/// the life of the order, including across amendments.
+pub type OrderId = u64;
+
+/// Identifies the account or trader an order belongs to.
+pub type AccountId = u32;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Side {
     Buy,
     Sell,
 }
 
+impl Side {
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
+/// How an order is priced.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum OrderKind {
+    /// Trades at `Order::price` or better; any remainder rests on the book.
+    #[default]
+    Limit,
+    /// Trades at whatever prices are resting on the other side until filled.
+    /// `Order::price` is ignored and any remainder is cancelled.
+    Market,
+    /// Waits in the trigger book until the last traded price reaches
+    /// `trigger` (at or above it for a buy, at or below it for a sell), then
+    /// enters as a market order.
+    Stop { trigger: Price },
+    /// Like `Stop`, but enters as a limit order at `Order::price`.
+    StopLimit { trigger: Price },
+    /// A `Stop` whose trigger follows the last traded price at a distance of
+    /// `trail`, moving only in the order's favour: up for a sell, down for a
+    /// buy. `trigger` is set when the order enters the book.
+    TrailingStop { trail: Trail, trigger: Price },
+}
+
+/// How far a trailing stop's trigger sits from the last traded price.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Trail {
+    /// A fixed distance in price.
+    Amount(Price),
+    /// A share of the last traded price, in hundredths of a percent.
+    BasisPoints(u32),
+}
+
+impl std::str::FromStr for Trail {
+    type Err = &'static str;
+
+    /// Parses a price distance (`0.5`) or basis points (`150bp`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.strip_suffix("bp") {
+            Some(basis_points) => basis_points
+                .trim()
+                .parse()
+                .map(Trail::BasisPoints)
+                .map_err(|_| "trail must be a price or basis points, e.g. 0.5 or 150bp"),
+            None => s
+                .parse()
+                .map(Trail::Amount)
+                .map_err(|_| "trail must be a price or basis points, e.g. 0.5 or 150bp"),
+        }
+    }
+}
+
+impl Trail {
+    /// Distance from `price` to the trigger, never less than the smallest
+    /// price step.
+    pub fn distance(self, price: Price) -> Price {
+        let distance = match self {
+            Trail::Amount(amount) => amount,
+            Trail::BasisPoints(basis_points) => {
+                let units = i128::from(price.units()) * i128::from(basis_points) / 10_000;
+                Price::from_units(units.clamp(0, i128::from(i64::MAX)) as i64)
+            }
+        };
+        distance.max(Price::EPSILON)
+    }
+}
+
+/// How long an order stays working before whatever is left of it expires.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum TimeInForce {
+    /// Good till cancelled: rests until filled or cancelled.
+    #[default]
+    Gtc,
+    /// Immediate or cancel: trades what it can on entry, the rest is cancelled.
+    Ioc,
+    /// Fill or kill: trades its whole quantity on entry or nothing at all.
+    Fok,
+    /// Rests until `Trade::end_session`.
+    Day,
+    /// Good till date: rests until the book's clock reaches the given time.
+    Gtd(Timestamp),
+}
+
+/// The book price a pegged order follows.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PegReference {
+    /// Best price on the order's own side: best bid for a buy.
+    Primary,
+    /// Best price on the other side: best offer for a buy.
+    Market,
+    /// Halfway between best bid and best offer, rounded away from the other
+    /// side.
+    Midpoint,
+}
+
+/// Pricing of a pegged order: `reference` plus `offset`, capped at `limit`
+/// (no higher for a buy, no lower for a sell).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Peg {
+    pub reference: PegReference,
+    pub offset: Price,
+    pub limit: Option<Price>,
+}
+
+/// What to do with a post-only order that would trade on entry.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PostOnly {
+    /// Reject the order.
+    Reject,
+    /// Move the price one tick away from the other side so it rests instead.
+    Reprice,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Order {
-    pub order_type: OrderType,
-    pub price: i32,
+    /// Zero until the order is entered through `Trade::submit`.
+    pub id: OrderId,
+    /// Owner of the order. Orders from the same account never trade with
+    /// each other; see `SelfTradePrevention`.
+    pub account: Option<AccountId>,
+    pub side: Side,
+    pub kind: OrderKind,
+    pub price: Price,
+    pub time_in_force: TimeInForce,
+    /// Set for orders whose price follows the book; `price` then holds the
+    /// price the order was last pegged at.
+    pub peg: Option<Peg>,
+    /// Set for orders that must only add liquidity, never take it.
+    pub post_only: Option<PostOnly>,
+    /// Quantity still open on the order and visible in the book. Partial
+    /// fills reduce it in place.
+    pub quantity: u32,
+    /// Iceberg reserve: open quantity not shown in the book. It replenishes
+    /// `quantity` in slices of `display_quantity` once the visible part fills.
+    pub hidden_quantity: u32,
+    /// Size of each visible slice of an iceberg order; `None` for an order
+    /// that is shown in full.
+    pub display_quantity: Option<u32>,
+    /// Time priority within a price level: lower sequence numbers arrived
+    /// earlier and are matched first. Zero until the order enters a book.
+    pub sequence: u64,
+    /// Sequence at which the order last entered the book. Unlike `sequence`
+    /// it is kept when an iceberg refills, so it tells which of two crossing
+    /// orders was resting.
+    pub entry_sequence: u64,
+}
+
+impl Order {
+    /// A limit order.
+    pub fn new(side: Side, price: impl Into<Price>, quantity: u32) -> Self {
+        Self {
+            id: 0,
+            account: None,
+            side,
+            kind: OrderKind::Limit,
+            price: price.into(),
+            time_in_force: TimeInForce::Gtc,
+            peg: None,
+            post_only: None,
+            quantity,
+            hidden_quantity: 0,
+            display_quantity: None,
+            sequence: 0,
+            entry_sequence: 0,
+        }
+    }
+
+    /// A market order. Its remainder never rests, so it is immediate or cancel.
+    pub fn market(side: Side, quantity: u32) -> Self {
+        Self {
+            kind: OrderKind::Market,
+            time_in_force: TimeInForce::Ioc,
+            ..Self::new(side, 0, quantity)
+        }
+    }
+
+    /// A stop order that becomes a market order once triggered.
+    pub fn stop(side: Side, trigger: impl Into<Price>, quantity: u32) -> Self {
+        Self {
+            kind: OrderKind::Stop {
+                trigger: trigger.into(),
+            },
+            ..Self::new(side, 0, quantity)
+        }
+    }
+
+    /// A stop order that becomes a limit order at `price` once triggered.
+    pub fn stop_limit(
+        side: Side,
+        trigger: impl Into<Price>,
+        price: impl Into<Price>,
+        quantity: u32,
+    ) -> Self {
+        Self {
+            kind: OrderKind::StopLimit {
+                trigger: trigger.into(),
+            },
+            ..Self::new(side, price, quantity)
+        }
+    }
+
+    /// A limit order priced from the book. See `Peg`.
+    pub fn pegged(side: Side, peg: Peg, quantity: u32) -> Self {
+        Self {
+            peg: Some(peg),
+            ..Self::new(side, 0, quantity)
+        }
+    }
+
+    /// A trailing stop that becomes a market order once triggered.
+    pub fn trailing_stop(side: Side, trail: Trail, quantity: u32) -> Self {
+        Self {
+            kind: OrderKind::TrailingStop {
+                trail,
+                trigger: Price::ZERO,
+            },
+            ..Self::new(side, 0, quantity)
+        }
+    }
+
+    pub fn with_time_in_force(self, time_in_force: TimeInForce) -> Self {
+        Self {
+            time_in_force,
+            ..self
+        }
+    }
+
+    pub fn with_account(self, account: AccountId) -> Self {
+        Self {
+            account: Some(account),
+            ..self
+        }
+    }
+
+    pub fn with_post_only(self, post_only: PostOnly) -> Self {
+        Self {
+            post_only: Some(post_only),
+            ..self
+        }
+    }
+
+    /// Makes this an iceberg order that shows at most `display_quantity` at
+    /// a time and keeps the rest of its quantity in reserve.
+    pub fn with_display_quantity(self, display_quantity: u32) -> Self {
+        // An order too large to total is left unsplit and fails entry.
+        let Some(total) = self.checked_total_quantity() else {
+            return Self {
+                display_quantity: Some(display_quantity),
+                ..self
+            };
+        };
+        let quantity = total.min(display_quantity);
+        Self {
+            quantity,
+            hidden_quantity: total - quantity,
+            display_quantity: Some(display_quantity),
+            ..self
+        }
+    }
+
+    /// Visible and hidden quantity together. Saturates at `u32::MAX`; see
+    /// `checked_total_quantity`.
+    pub fn total_quantity(&self) -> u32 {
+        self.quantity.saturating_add(self.hidden_quantity)
+    }
+
+    /// Visible and hidden quantity together, or `None` if that overflows.
+    /// Orders that overflow are rejected on entry.
+    pub fn checked_total_quantity(&self) -> Option<u32> {
+        self.quantity.checked_add(self.hidden_quantity)
+    }
+
+    /// Moves the next slice of an iceberg's reserve into view once the
+    /// visible quantity has been used up. Returns false if there is nothing
+    /// left to show.
+    pub fn replenish(&mut self) -> bool {
+        let Some(display_quantity) = self.display_quantity else {
+            return false;
+        };
+        let slice = display_quantity.min(self.hidden_quantity);
+        self.quantity += slice;
+        self.hidden_quantity -= slice;
+        slice > 0
+    }
+
+    /// The trigger price of a stop, stop-limit or trailing stop order.
+    pub fn trigger(&self) -> Option<Price> {
+        match self.kind {
+            OrderKind::Stop { trigger }
+            | OrderKind::StopLimit { trigger }
+            | OrderKind::TrailingStop { trigger, .. } => Some(trigger),
+            OrderKind::Limit | OrderKind::Market => None,
+        }
+    }
+
+    /// Whether the order only trades on entry and never rests on the book.
+    pub fn is_immediate(&self) -> bool {
+        self.kind == OrderKind::Market
+            || matches!(self.time_in_force, TimeInForce::Ioc | TimeInForce::Fok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trail_parses_ticks_and_basis_points() {
+        assert_eq!("5".parse(), Ok(Trail::Amount(Price::from(5))));
+        assert_eq!("0.5".parse(), Ok(Trail::Amount("0.5".parse().unwrap())));
+        assert_eq!(" 150bp ".parse(), Ok(Trail::BasisPoints(150)));
+        assert!("1.5%".parse::<Trail>().is_err());
+    }
+
+    #[test]
+    fn trail_distance_is_at_least_one_tick() {
+        let price = Price::from(250);
+        assert_eq!(Trail::BasisPoints(100).distance(price).to_string(), "2.50");
+        assert_eq!(
+            Trail::BasisPoints(1).distance(Price::from(50)),
+            Price::EPSILON
+        );
+        assert_eq!(Trail::Amount(Price::ZERO).distance(price), Price::EPSILON);
+        assert_eq!(
+            Trail::Amount(Price::from(7)).distance(price),
+            Price::from(7)
+        );
+    }
 }
@@ -1,56 +1,280 @@
-use crate::{Order, OrderType};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 
+use crate::{InstrumentRules, Order, OrderId, OrderKind, Price, PriceLevel, Side};
+
+/// One side of a book, organised as price levels. Each level is a FIFO queue
+/// of the orders resting at that price, so orders are kept best-first in
+/// price-time priority: buys by highest price, sells by lowest price, and
+/// orders at the same price by ascending `Order::sequence`.
 #[derive(Clone, Debug, PartialEq)]
 pub struct OrdersVec {
-    order_type: OrderType,
-    orders: Vec<Order>,
+    side: Side,
+    levels: BTreeMap<Price, VecDeque<Order>>,
+    // Price level of every resting order, for lookups by id.
+    prices: BTreeMap<OrderId, Price>,
+    // Resting orders that are pegged, so repricing need not walk the book.
+    pegged: BTreeSet<OrderId>,
+    // Cached so the best price can be read without walking the tree.
+    best_price: Option<Price>,
+    len: usize,
+    // Levels changed since the last `take_changes`, as they were before.
+    changes: BTreeMap<Price, Option<PriceLevel>>,
 }
 
 impl OrdersVec {
-    pub fn new(order_type: OrderType) -> Self {
-        Self { order_type, orders: Vec::new() }
+    pub fn new(side: Side) -> Self {
+        Self {
+            side,
+            levels: BTreeMap::new(),
+            prices: BTreeMap::new(),
+            pegged: BTreeSet::new(),
+            best_price: None,
+            len: 0,
+            changes: BTreeMap::new(),
+        }
+    }
+
+    /// Validates a newly entered order against `rules` and pushes it.
+    pub fn add_order(&mut self, order: Order, rules: &InstrumentRules) -> Result<(), &'static str> {
+        rules.check(&order)?;
+        // Propagate the error from `push` instead of unwrapping.
+        self.push(order)
     }
 
-    pub fn add_order(&mut self, price: i32) -> Result<(), &'static str> {
-        if price <= 0 {
-            Err("price cannot be negative")
+    /// Rests `order` on this side. Ids must be unique on the side; an order
+    /// with id 0, as built by `Order::new`, is given the id after the highest
+    /// one resting here.
+    pub fn push(&mut self, mut order: Order) -> Result<(), &'static str> {
+        if order.id == 0 {
+            order.id = self.prices.last_key_value().map_or(1, |(&id, _)| id + 1);
+        }
+        if order.side != self.side {
+            Err("order side does not match OrdersVec side")
+        } else if order.kind == OrderKind::Market {
+            Err("market orders cannot rest on the book")
+        } else if order.trigger().is_some() {
+            Err("stop orders cannot rest on the book")
+        } else if order.is_immediate() {
+            Err("immediate orders cannot rest on the book")
+        } else if order.quantity == 0 {
+            Err("quantity must be positive")
+        } else if self.prices.contains_key(&order.id) {
+            Err("duplicate order id")
         } else {
-            let new_order = Order {
-                order_type: self.order_type.clone(),
-                price,
-            };
-            // Propagate the error from `push` instead of unwrapping.
-            self.push(new_order)
+            let price = order.price;
+            self.touch(price);
+            let level = self.levels.entry(price).or_default();
+            // New orders normally carry the highest sequence and go straight
+            // to the back; ties keep the order they were pushed in.
+            let index = level.partition_point(|o| o.sequence <= order.sequence);
+            self.prices.insert(order.id, price);
+            if order.peg.is_some() {
+                self.pegged.insert(order.id);
+            }
+            level.insert(index, order);
+            self.len += 1;
+
+            if self
+                .best_price
+                .is_none_or(|best| self.ranks_before(price, best))
+            {
+                self.best_price = Some(price);
+            }
+            Ok(())
         }
     }
 
-    pub fn push(&mut self, order: Order) -> Result<(), &'static str> {
-        if order.order_type == self.order_type {
-            self.orders.push(order);
-            self.orders.sort_by_key(|o| o.price);
-            Ok(())
-        } else {
-            Err("order type does not match OrdersVec type")
+    /// Notes that the level at `price` is about to change, keeping how it
+    /// looked before the first change.
+    fn touch(&mut self, price: Price) {
+        if !self.changes.contains_key(&price) {
+            let before = self
+                .levels
+                .get(&price)
+                .map(|orders| PriceLevel::new(price, orders));
+            self.changes.insert(price, before);
+        }
+    }
+
+    /// Levels whose visible quantity or order count changed since the last
+    /// call, as `(before, after)`. `None` is a level with no orders.
+    pub(crate) fn take_changes(&mut self) -> Vec<(Option<PriceLevel>, Option<PriceLevel>)> {
+        let changes = std::mem::take(&mut self.changes);
+        changes
+            .into_iter()
+            .map(|(price, before)| {
+                let after = self
+                    .levels
+                    .get(&price)
+                    .map(|orders| PriceLevel::new(price, orders));
+                (before, after)
+            })
+            .filter(|(before, after)| before != after)
+            .collect()
+    }
+
+    /// Whether a level at price `a` is matched before one at price `b`.
+    fn ranks_before(&self, a: Price, b: Price) -> bool {
+        match self.side {
+            Side::Buy => a > b,
+            Side::Sell => a < b,
         }
     }
 
     pub fn len(&self) -> usize {
-        self.orders.len()
+        self.len
     }
 
     pub fn is_empty(&self) -> bool {
-        self.orders.is_empty()
+        self.len == 0
+    }
+
+    /// The best price on this side: the highest buy or the lowest sell.
+    pub fn best_price(&self) -> Option<Price> {
+        self.best_price
+    }
+
+    /// The order with the highest priority on this side, if any.
+    pub fn best(&self) -> Option<&Order> {
+        self.levels.get(&self.best_price?)?.front()
+    }
+
+    /// Best price among orders that are not pegged. Pegged orders take their
+    /// price from this, so they never follow each other or themselves.
+    pub fn best_unpegged_price(&self) -> Option<Price> {
+        if self.pegged.is_empty() {
+            return self.best_price;
+        }
+        // Stops at the first unpegged order, so only pegged orders ahead of
+        // it are passed over.
+        self.iter()
+            .find(|order| order.peg.is_none())
+            .map(|order| order.price)
+    }
+
+    /// The pegged orders on this side, by id.
+    pub fn pegged(&self) -> impl Iterator<Item = &Order> {
+        self.pegged.iter().filter_map(|&id| self.get(id))
+    }
+
+    /// Price levels best-first, each with its orders in time priority.
+    pub fn levels(&self) -> Box<dyn Iterator<Item = (Price, &VecDeque<Order>)> + '_> {
+        let levels = self.levels.iter().map(|(price, orders)| (*price, orders));
+        match self.side {
+            Side::Buy => Box::new(levels.rev()),
+            Side::Sell => Box::new(levels),
+        }
+    }
+
+    /// Visible quantity at the best price, without walking the other levels.
+    pub fn top(&self) -> Option<PriceLevel> {
+        let price = self.best_price?;
+        Some(PriceLevel::new(price, self.levels.get(&price)?))
+    }
+
+    /// Visible quantity at each of the best `levels` prices, best first.
+    pub fn depth(&self, levels: usize) -> Vec<PriceLevel> {
+        self.levels()
+            .take(levels)
+            .map(|(price, orders)| PriceLevel::new(price, orders))
+            .collect()
+    }
+
+    /// The orders resting at exactly `price`, in time priority.
+    pub fn level(&self, price: Price) -> Option<&VecDeque<Order>> {
+        self.levels.get(&price)
+    }
+
+    /// Every order on this side in price-time priority.
+    pub fn iter(&self) -> impl Iterator<Item = &Order> {
+        self.levels().flat_map(|(_, orders)| orders.iter())
+    }
+
+    pub fn get(&self, id: OrderId) -> Option<&Order> {
+        let price = self.prices.get(&id)?;
+        self.levels.get(price)?.iter().find(|o| o.id == id)
+    }
+
+    fn get_mut(&mut self, id: OrderId) -> Option<&mut Order> {
+        let price = self.prices.get(&id)?;
+        self.levels.get_mut(price)?.iter_mut().find(|o| o.id == id)
+    }
+
+    pub fn cancel(&mut self, id: OrderId) -> Option<Order> {
+        let price = *self.prices.get(&id)?;
+        self.touch(price);
+        self.prices.remove(&id);
+        self.pegged.remove(&id);
+        let level = self.levels.get_mut(&price)?;
+        let index = level.iter().position(|o| o.id == id)?;
+        let order = level.remove(index);
+        self.len -= 1;
+
+        if level.is_empty() {
+            self.levels.remove(&price);
+            if self.best_price == Some(price) {
+                self.best_price = match self.side {
+                    Side::Buy => self.levels.keys().next_back().copied(),
+                    Side::Sell => self.levels.keys().next().copied(),
+                };
+            }
+        }
+        order
     }
 
-    pub fn as_slice(&self) -> &[Order] {
-        &self.orders
+    /// Takes up to `quantity` off the order with the given id, removing the
+    /// order once nothing is left open. The remainder keeps its place in the
+    /// queue. Returns the quantity actually filled.
+    pub fn fill(&mut self, id: OrderId, quantity: u32) -> u32 {
+        let Some(&price) = self.prices.get(&id) else {
+            return 0;
+        };
+        self.touch(price);
+        let order = self.get_mut(id).unwrap();
+        let filled = quantity.min(order.quantity);
+        order.quantity -= filled;
+        if order.quantity == 0 {
+            self.cancel(id);
+        }
+        filled
     }
 
-    pub fn remove(&mut self, index: usize) -> Option<Order> {
-        if index < self.orders.len() {
-            Some(self.orders.remove(index))
+    /// Changes the price and total quantity of a resting order, hidden
+    /// reserve included. Reducing the quantity at the same price keeps the
+    /// order's place in the queue, showing no more than it did; any price
+    /// change or quantity increase loses it, and the order is re-queued with
+    /// `new_sequence` as if it had just arrived, showing a fresh slice if it
+    /// is an iceberg. The new price is not checked; see
+    /// `InstrumentRules::check_price`.
+    pub fn amend(
+        &mut self,
+        id: OrderId,
+        new_price: impl Into<Price>,
+        new_quantity: u32,
+        new_sequence: u64,
+    ) -> Result<(), &'static str> {
+        let new_price = new_price.into();
+        let order = self.get(id).ok_or("order not found")?;
+        if new_quantity == 0 {
+            return Err("quantity must be positive");
+        }
+
+        if new_price == order.price && new_quantity <= order.total_quantity() {
+            self.touch(new_price);
+            let order = self.get_mut(id).unwrap();
+            order.quantity = order.quantity.min(new_quantity);
+            order.hidden_quantity = new_quantity - order.quantity;
+            Ok(())
         } else {
-            None
+            let mut order = self.cancel(id).unwrap();
+            order.price = new_price;
+            order.quantity = order
+                .display_quantity
+                .map_or(new_quantity, |display| display.min(new_quantity));
+            order.hidden_quantity = new_quantity - order.quantity;
+            order.sequence = new_sequence;
+            order.entry_sequence = new_sequence;
+            self.push(order)
         }
     }
 }
@@ -58,169 +282,378 @@ impl OrdersVec {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::TimeInForce;
+
+    fn order_with(id: OrderId, sequence: u64, price: i32, quantity: u32) -> Order {
+        let mut order = Order::new(Side::Buy, price, quantity);
+        order.id = id;
+        order.sequence = sequence;
+        order
+    }
+
+    fn sell_with(id: OrderId, sequence: u64, price: i32, quantity: u32) -> Order {
+        let mut order = Order::new(Side::Sell, price, quantity);
+        order.id = id;
+        order.sequence = sequence;
+        order
+    }
 
     #[test]
     fn test_new_creates_empty_ordersvec() {
-        let orders_vec = OrdersVec::new(OrderType::Buy);
+        let orders_vec = OrdersVec::new(Side::Buy);
         assert!(orders_vec.is_empty());
         assert_eq!(orders_vec.len(), 0);
+        assert!(orders_vec.best().is_none());
+        assert!(orders_vec.best_price().is_none());
     }
 
     #[test]
     fn test_add_order_with_valid_price() {
-        let mut orders_vec = OrdersVec::new(OrderType::Buy);
-        let result = orders_vec.add_order(100);
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        let result =
+            orders_vec.add_order(Order::new(Side::Buy, 100, 10), &InstrumentRules::default());
         assert!(result.is_ok());
         assert_eq!(orders_vec.len(), 1);
     }
 
     #[test]
     fn test_add_order_with_zero_price() {
-        let mut orders_vec = OrdersVec::new(OrderType::Buy);
-        let result = orders_vec.add_order(0);
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        let result =
+            orders_vec.add_order(Order::new(Side::Buy, 0, 10), &InstrumentRules::default());
         assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "price cannot be negative");
+        assert_eq!(result.unwrap_err(), "price must be positive");
         assert!(orders_vec.is_empty());
     }
 
     #[test]
     fn test_add_order_with_negative_price() {
-        let mut orders_vec = OrdersVec::new(OrderType::Buy);
-        let result = orders_vec.add_order(-50);
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        let result =
+            orders_vec.add_order(Order::new(Side::Buy, -50, 10), &InstrumentRules::default());
         assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "price cannot be negative");
+        assert_eq!(result.unwrap_err(), "price must be positive");
         assert!(orders_vec.is_empty());
     }
 
     #[test]
-    fn test_push_matching_order_type_succeeds() {
-        let mut orders_vec = OrdersVec::new(OrderType::Buy);
-        let order = Order {
-            order_type: OrderType::Buy,
-            price: 150,
-        };
+    fn test_add_order_with_zero_quantity() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        let result =
+            orders_vec.add_order(Order::new(Side::Buy, 100, 0), &InstrumentRules::default());
+        assert_eq!(result.unwrap_err(), "quantity must be positive");
+        assert!(orders_vec.is_empty());
+    }
+
+    #[test]
+    fn test_push_matching_side_succeeds() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        let order = Order::new(Side::Buy, 150, 10);
         let result = orders_vec.push(order);
         assert!(result.is_ok());
         assert_eq!(orders_vec.len(), 1);
     }
 
     #[test]
-    fn test_push_non_matching_order_type_fails() {
-        let mut orders_vec = OrdersVec::new(OrderType::Buy);
-        let order = Order {
-            order_type: OrderType::Sell,
-            price: 150,
-        };
+    fn test_push_non_matching_side_fails() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        let order = Order::new(Side::Sell, 150, 10);
         let result = orders_vec.push(order);
         assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "order type does not match OrdersVec type");
+        assert_eq!(
+            result.unwrap_err(),
+            "order side does not match OrdersVec side"
+        );
         assert!(orders_vec.is_empty());
     }
 
     #[test]
-    fn test_orders_are_sorted_by_price() {
-        let mut orders_vec = OrdersVec::new(OrderType::Buy);
-        orders_vec
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 150,
-            })
-            .ok();
-        orders_vec
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 100,
-            })
-            .ok();
-        orders_vec
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 200,
-            })
-            .ok();
+    fn test_push_market_order_fails() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        let result = orders_vec.push(Order::market(Side::Buy, 10));
+        assert_eq!(result.unwrap_err(), "market orders cannot rest on the book");
+        assert!(orders_vec.is_empty());
+    }
 
-        let slice = orders_vec.as_slice();
-        assert_eq!(slice[0].price, 100);
-        assert_eq!(slice[1].price, 150);
-        assert_eq!(slice[2].price, 200);
+    #[test]
+    fn test_push_immediate_order_fails() {
+        let mut orders_vec = OrdersVec::new(Side::Sell);
+        let ioc = Order::new(Side::Sell, 50, 10).with_time_in_force(TimeInForce::Ioc);
+        let result = orders_vec.push(ioc);
+        assert_eq!(
+            result.unwrap_err(),
+            "immediate orders cannot rest on the book"
+        );
+        assert!(orders_vec.is_empty());
     }
 
     #[test]
-    fn test_as_slice_returns_orders() {
-        let mut orders_vec = OrdersVec::new(OrderType::Buy);
-        orders_vec
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 100,
-            })
-            .ok();
-        orders_vec
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 200,
-            })
-            .ok();
+    fn test_push_stop_order_fails() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        let result = orders_vec.push(Order::stop_limit(Side::Buy, 60, 61, 10));
+        assert_eq!(result.unwrap_err(), "stop orders cannot rest on the book");
+        assert!(orders_vec.is_empty());
+    }
 
-        let slice = orders_vec.as_slice();
-        assert_eq!(slice.len(), 2);
-        assert_eq!(slice[0].price, 100);
-        assert_eq!(slice[1].price, 200);
+    #[test]
+    fn test_push_assigns_ids_to_new_orders() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        orders_vec.push(Order::new(Side::Buy, 100, 10)).unwrap();
+        orders_vec.push(Order::new(Side::Buy, 101, 10)).unwrap();
+
+        let mut ids: Vec<OrderId> = orders_vec.iter().map(|o| o.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2]);
     }
 
     #[test]
-    fn test_remove_valid_index() {
-        let mut orders_vec = OrdersVec::new(OrderType::Buy);
-        orders_vec
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 100,
-            })
-            .ok();
+    fn test_push_duplicate_id_fails() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        orders_vec.push(order_with(1, 1, 100, 10)).unwrap();
+        let result = orders_vec.push(order_with(1, 2, 101, 10));
+        assert_eq!(result.unwrap_err(), "duplicate order id");
+        assert_eq!(orders_vec.len(), 1);
+    }
+
+    #[test]
+    fn test_buy_orders_are_sorted_best_price_first() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        orders_vec.push(order_with(1, 1, 150, 10)).ok();
+        orders_vec.push(order_with(2, 2, 100, 10)).ok();
+        orders_vec.push(order_with(3, 3, 200, 10)).ok();
+
+        let prices: Vec<Price> = orders_vec.iter().map(|o| o.price).collect();
+        assert_eq!(prices, [200, 150, 100].map(Price::from));
+        assert_eq!(orders_vec.best().unwrap().price, Price::from(200));
+        assert_eq!(orders_vec.best_price(), Some(Price::from(200)));
+    }
+
+    #[test]
+    fn test_sell_orders_are_sorted_best_price_first() {
+        let mut orders_vec = OrdersVec::new(Side::Sell);
+        orders_vec.push(sell_with(1, 1, 150, 10)).ok();
+        orders_vec.push(sell_with(2, 2, 100, 10)).ok();
+        orders_vec.push(sell_with(3, 3, 200, 10)).ok();
+
+        let prices: Vec<Price> = orders_vec.iter().map(|o| o.price).collect();
+        assert_eq!(prices, [100, 150, 200].map(Price::from));
+        assert_eq!(orders_vec.best_price(), Some(Price::from(100)));
+    }
+
+    #[test]
+    fn test_same_price_is_ordered_by_sequence_not_push_order() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        orders_vec.push(order_with(1, 7, 100, 10)).ok();
+        orders_vec.push(order_with(2, 3, 100, 10)).ok();
+        orders_vec.push(order_with(3, 5, 100, 10)).ok();
+
+        let ids: Vec<OrderId> = orders_vec.iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn test_levels_group_orders_by_price() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        orders_vec.push(order_with(1, 1, 100, 10)).ok();
+        orders_vec.push(order_with(2, 2, 200, 5)).ok();
+        orders_vec.push(order_with(3, 3, 100, 7)).ok();
+
+        let levels: Vec<(Price, Vec<OrderId>)> = orders_vec
+            .levels()
+            .map(|(price, orders)| (price, orders.iter().map(|o| o.id).collect()))
+            .collect();
+        assert_eq!(
+            levels,
+            vec![(Price::from(200), vec![2]), (Price::from(100), vec![1, 3])]
+        );
+        assert_eq!(orders_vec.level(Price::from(100)).unwrap().len(), 2);
+        assert!(orders_vec.level(Price::from(150)).is_none());
+    }
+
+    #[test]
+    fn test_iter_returns_orders() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        orders_vec.push(order_with(1, 1, 100, 10)).ok();
+        orders_vec.push(order_with(2, 2, 200, 10)).ok();
+
+        let orders: Vec<&Order> = orders_vec.iter().collect();
+        assert_eq!(orders.len(), 2);
+        assert_eq!(orders[0].price, Price::from(200));
+        assert_eq!(orders[1].price, Price::from(100));
+    }
+
+    #[test]
+    fn test_fill_partial_keeps_remainder_in_place() {
+        let mut orders_vec = OrdersVec::new(Side::Sell);
+        orders_vec.push(sell_with(1, 1, 100, 10)).ok();
+        orders_vec.push(sell_with(2, 2, 100, 10)).ok();
+
+        assert_eq!(orders_vec.fill(1, 4), 4);
+        assert_eq!(orders_vec.len(), 2);
+        assert_eq!(orders_vec.best().unwrap().id, 1);
+        assert_eq!(orders_vec.best().unwrap().quantity, 6);
+    }
+
+    #[test]
+    fn test_fill_complete_removes_order() {
+        let mut orders_vec = OrdersVec::new(Side::Sell);
+        orders_vec.push(sell_with(1, 1, 100, 10)).ok();
+
+        assert_eq!(orders_vec.fill(1, 25), 10);
+        assert!(orders_vec.is_empty());
+        assert!(orders_vec.level(Price::from(100)).is_none());
+        assert_eq!(orders_vec.fill(1, 5), 0);
+    }
+
+    #[test]
+    fn test_get_finds_order_by_id() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
         orders_vec
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 200,
-            })
-            .ok();
+            .add_order(order_with(7, 1, 100, 10), &InstrumentRules::default())
+            .unwrap();
+        assert_eq!(orders_vec.get(7).unwrap().price, Price::from(100));
+        assert!(orders_vec.get(8).is_none());
+    }
+
+    #[test]
+    fn test_cancel_by_id_survives_resorting() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        orders_vec.push(order_with(1, 1, 200, 10)).ok();
+        orders_vec.push(order_with(2, 2, 100, 10)).ok();
 
-        let removed = orders_vec.remove(0);
-        assert!(removed.is_some());
-        assert_eq!(removed.unwrap().price, 100);
+        let cancelled = orders_vec.cancel(1).unwrap();
+        assert_eq!(cancelled.price, Price::from(200));
         assert_eq!(orders_vec.len(), 1);
+        assert!(orders_vec.cancel(1).is_none());
     }
 
     #[test]
-    fn test_remove_invalid_index() {
-        let mut orders_vec = OrdersVec::new(OrderType::Buy);
-        orders_vec
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 100,
-            })
-            .ok();
+    fn test_cancel_best_level_moves_best_price() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        orders_vec.push(order_with(1, 1, 200, 10)).ok();
+        orders_vec.push(order_with(2, 2, 100, 10)).ok();
+        orders_vec.push(order_with(3, 3, 150, 10)).ok();
+
+        orders_vec.cancel(1);
+        assert_eq!(orders_vec.best_price(), Some(Price::from(150)));
+        orders_vec.cancel(3);
+        assert_eq!(orders_vec.best_price(), Some(Price::from(100)));
+        orders_vec.cancel(2);
+        assert_eq!(orders_vec.best_price(), None);
+    }
+
+    #[test]
+    fn test_cancel_unknown_id() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        orders_vec.push(order_with(1, 1, 100, 10)).ok();
 
-        let removed = orders_vec.remove(5);
-        assert!(removed.is_none());
+        assert!(orders_vec.cancel(5).is_none());
         assert_eq!(orders_vec.len(), 1);
     }
 
     #[test]
-    fn test_remove_from_empty() {
-        let mut orders_vec = OrdersVec::new(OrderType::Buy);
-        let removed = orders_vec.remove(0);
-        assert!(removed.is_none());
+    fn test_cancel_from_empty() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        assert!(orders_vec.cancel(1).is_none());
         assert!(orders_vec.is_empty());
     }
 
+    #[test]
+    fn test_amend_reduce_quantity_keeps_priority() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        orders_vec.push(order_with(1, 1, 100, 10)).ok();
+        orders_vec.push(order_with(2, 2, 100, 10)).ok();
+
+        orders_vec.amend(1, 100, 4, 3).unwrap();
+        let best = orders_vec.best().unwrap();
+        assert_eq!(best.id, 1);
+        assert_eq!(best.quantity, 4);
+        assert_eq!(best.sequence, 1);
+    }
+
+    #[test]
+    fn test_amend_increase_quantity_loses_priority() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        orders_vec.push(order_with(1, 1, 100, 10)).ok();
+        orders_vec.push(order_with(2, 2, 100, 10)).ok();
+
+        orders_vec.amend(1, 100, 15, 3).unwrap();
+        let ids: Vec<OrderId> = orders_vec.iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+        assert_eq!(orders_vec.get(1).unwrap().quantity, 15);
+    }
+
+    #[test]
+    fn test_amend_price_change_loses_priority() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        orders_vec.push(order_with(1, 1, 100, 10)).ok();
+        orders_vec.push(order_with(2, 2, 90, 10)).ok();
+
+        orders_vec.amend(1, 90, 5, 3).unwrap();
+        let ids: Vec<OrderId> = orders_vec.iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+        assert_eq!(orders_vec.get(1).unwrap().sequence, 3);
+        assert!(orders_vec.level(Price::from(100)).is_none());
+    }
+
+    fn iceberg_with(id: OrderId, sequence: u64, quantity: u32, display: u32) -> Order {
+        let mut order = Order::new(Side::Sell, 100, quantity).with_display_quantity(display);
+        order.id = id;
+        order.sequence = sequence;
+        order
+    }
+
+    #[test]
+    fn test_amend_iceberg_reduction_keeps_the_visible_slice() {
+        let mut orders_vec = OrdersVec::new(Side::Sell);
+        orders_vec.push(iceberg_with(1, 1, 20, 5)).unwrap();
+        orders_vec.fill(1, 3);
+
+        // 2 showing, 15 hidden; the new total comes out of the reserve first
+        orders_vec.amend(1, 100, 10, 2).unwrap();
+        let order = orders_vec.get(1).unwrap();
+        assert_eq!((order.quantity, order.hidden_quantity), (2, 8));
+        assert_eq!(order.sequence, 1);
+
+        orders_vec.amend(1, 100, 1, 3).unwrap();
+        let order = orders_vec.get(1).unwrap();
+        assert_eq!((order.quantity, order.hidden_quantity), (1, 0));
+    }
+
+    #[test]
+    fn test_amend_iceberg_requeue_shows_one_slice() {
+        let mut orders_vec = OrdersVec::new(Side::Sell);
+        orders_vec.push(iceberg_with(1, 1, 20, 5)).unwrap();
+        orders_vec.push(sell_with(2, 2, 100, 10)).unwrap();
+
+        orders_vec.amend(1, 100, 30, 3).unwrap();
+        let order = orders_vec.get(1).unwrap();
+        assert_eq!((order.quantity, order.hidden_quantity), (5, 25));
+        let ids: Vec<OrderId> = orders_vec.iter().map(|o| o.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+
+        orders_vec.amend(1, 101, 3, 4).unwrap();
+        let order = orders_vec.get(1).unwrap();
+        assert_eq!((order.quantity, order.hidden_quantity), (3, 0));
+    }
+
+    #[test]
+    fn test_amend_rejects_unknown_id_and_bad_values() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        orders_vec.push(order_with(1, 1, 100, 10)).ok();
+
+        assert_eq!(orders_vec.amend(9, 100, 5, 3), Err("order not found"));
+        assert_eq!(
+            orders_vec.amend(1, 100, 0, 3),
+            Err("quantity must be positive")
+        );
+        assert_eq!(orders_vec.get(1).unwrap().quantity, 10);
+    }
+
     #[test]
     fn test_clone() {
-        let mut orders_vec = OrdersVec::new(OrderType::Buy);
-        orders_vec
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 100,
-            })
-            .ok();
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        orders_vec.push(Order::new(Side::Buy, 100, 10)).ok();
 
         let cloned = orders_vec.clone();
         assert_eq!(orders_vec, cloned);
@@ -228,22 +661,49 @@ mod tests {
     }
 
     #[test]
-    fn test_add_order_creates_correct_order_type() {
-        let mut buy_orders = OrdersVec::new(OrderType::Buy);
-        buy_orders.add_order(100).ok();
-        
-        let slice = buy_orders.as_slice();
-        assert_eq!(slice[0].order_type, OrderType::Buy);
+    fn test_add_order_creates_correct_side() {
+        let mut buy_orders = OrdersVec::new(Side::Buy);
+        buy_orders
+            .add_order(Order::new(Side::Buy, 100, 10), &InstrumentRules::default())
+            .ok();
+
+        assert_eq!(buy_orders.best().unwrap().side, Side::Buy);
     }
 
     #[test]
     fn test_add_order_with_sell_type() {
-        let mut sell_orders = OrdersVec::new(OrderType::Sell);
-        let result = sell_orders.add_order(100);
+        let mut sell_orders = OrdersVec::new(Side::Sell);
+        let result =
+            sell_orders.add_order(Order::new(Side::Sell, 100, 10), &InstrumentRules::default());
         assert!(result.is_ok());
         assert_eq!(sell_orders.len(), 1);
-        
-        let slice = sell_orders.as_slice();
-        assert_eq!(slice[0].order_type, OrderType::Sell);
+
+        assert_eq!(sell_orders.best().unwrap().side, Side::Sell);
+    }
+
+    #[test]
+    fn test_best_unpegged_price_skips_pegged_orders() {
+        let mut orders_vec = OrdersVec::new(Side::Buy);
+        let mut pegged = order_with(1, 1, 101, 10);
+        pegged.peg = Some(crate::Peg {
+            reference: crate::PegReference::Primary,
+            offset: Price::from(1),
+            limit: None,
+        });
+        orders_vec.push(pegged).unwrap();
+        orders_vec.push(order_with(2, 2, 100, 10)).unwrap();
+
+        assert_eq!(orders_vec.best_price(), Some(Price::from(101)));
+        assert_eq!(orders_vec.best_unpegged_price(), Some(Price::from(100)));
+        assert_eq!(
+            orders_vec.pegged().map(|o| o.id).collect::<Vec<_>>(),
+            vec![1]
+        );
+
+        orders_vec.amend(1, 99, 10, 3).unwrap();
+        assert_eq!(orders_vec.pegged().count(), 1);
+        orders_vec.cancel(1);
+        assert_eq!(orders_vec.pegged().count(), 0);
+        assert_eq!(orders_vec.best_unpegged_price(), Some(Price::from(100)));
     }
 }
@@ -0,0 +1,184 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A fixed-point price: a whole number of units of `10^-DECIMALS`. With the
+/// default of two decimal places, `101.25` is stored as 10125 units.
+///
+/// Arithmetic is checked and returns `None` on overflow rather than
+/// wrapping. Whole numbers convert with `From<i32>`, so `Price::from(101)`
+/// is `101.00`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Price<const DECIMALS: u32 = 2>(i64);
+
+impl<const DECIMALS: u32> Price<DECIMALS> {
+    /// Number of units in a whole price of 1.
+    pub const SCALE: i64 = 10_i64.pow(DECIMALS);
+    pub const ZERO: Self = Self(0);
+    pub const MAX: Self = Self(i64::MAX);
+    pub const MIN: Self = Self(i64::MIN);
+    /// The smallest step between two prices: one unit.
+    pub const EPSILON: Self = Self(1);
+
+    pub const fn from_units(units: i64) -> Self {
+        Self(units)
+    }
+
+    pub const fn units(self) -> i64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    pub fn checked_mul(self, factor: i64) -> Option<Self> {
+        self.0.checked_mul(factor).map(Self)
+    }
+
+    pub fn checked_neg(self) -> Option<Self> {
+        self.0.checked_neg().map(Self)
+    }
+
+    /// Distance between two prices, in units.
+    pub fn abs_diff(self, other: Self) -> u64 {
+        self.0.abs_diff(other.0)
+    }
+
+    /// The price `ticks` ticks of `tick_size` away from zero.
+    pub fn from_ticks(ticks: i64, tick_size: Self) -> Option<Self> {
+        tick_size.checked_mul(ticks)
+    }
+
+    /// Number of ticks of `tick_size` in this price, or `None` if the price
+    /// is not on a tick or the tick size is not positive.
+    pub fn to_ticks(self, tick_size: Self) -> Option<i64> {
+        if tick_size.0 <= 0 || self.0 % tick_size.0 != 0 {
+            return None;
+        }
+        Some(self.0 / tick_size.0)
+    }
+}
+
+impl<const DECIMALS: u32> From<i32> for Price<DECIMALS> {
+    fn from(whole: i32) -> Self {
+        Self(i64::from(whole) * Self::SCALE)
+    }
+}
+
+impl<const DECIMALS: u32> FromStr for Price<DECIMALS> {
+    type Err = &'static str;
+
+    /// Parses a decimal such as `101.25`, `-0.5` or `100`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let is_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        if whole.is_empty() && fraction.is_empty() || !is_digits(whole) || !is_digits(fraction) {
+            return Err("price must be a decimal number, e.g. 101.25");
+        }
+        if fraction.len() > DECIMALS as usize {
+            return Err("price has too many decimal places");
+        }
+
+        let whole: i64 = match whole {
+            "" => 0,
+            whole => whole.parse().map_err(|_| "price is out of range")?,
+        };
+        let fraction: i64 = format!("{:0<width$}", fraction, width = DECIMALS as usize)
+            .parse()
+            .unwrap_or(0);
+        let units = whole
+            .checked_mul(Self::SCALE)
+            .and_then(|units| units.checked_add(fraction))
+            .ok_or("price is out of range")?;
+        Ok(Self(if negative { -units } else { units }))
+    }
+}
+
+impl<const DECIMALS: u32> fmt::Display for Price<DECIMALS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        let scale = Self::SCALE.unsigned_abs();
+        if DECIMALS == 0 {
+            write!(f, "{}{}", sign, units)
+        } else {
+            let width = DECIMALS as usize;
+            write!(f, "{}{}.{:0width$}", sign, units / scale, units % scale)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_formats_decimals() {
+        let price: Price = "101.25".parse().unwrap();
+        assert_eq!(price.units(), 10125);
+        assert_eq!(price.to_string(), "101.25");
+        assert_eq!("-0.5".parse::<Price>().unwrap().to_string(), "-0.50");
+        assert_eq!(" 100 ".parse(), Ok(Price::<2>::from(100)));
+        assert_eq!(".5".parse::<Price>().unwrap().units(), 50);
+    }
+
+    #[test]
+    fn rejects_malformed_prices() {
+        assert!("".parse::<Price>().is_err());
+        assert!("1.2.3".parse::<Price>().is_err());
+        assert!("12a".parse::<Price>().is_err());
+        assert_eq!(
+            "1.005".parse::<Price>(),
+            Err("price has too many decimal places")
+        );
+        assert_eq!(
+            "99999999999999999999".parse::<Price>(),
+            Err("price is out of range")
+        );
+    }
+
+    #[test]
+    fn decimal_places_are_configurable() {
+        let price: Price<4> = "1.2345".parse().unwrap();
+        assert_eq!(price.units(), 12345);
+        assert_eq!(price.to_string(), "1.2345");
+        assert_eq!(Price::<0>::from(7).to_string(), "7");
+        assert_eq!(
+            "0.00001".parse::<Price<4>>(),
+            Err("price has too many decimal places")
+        );
+        assert_eq!("-0.0001".parse::<Price<4>>().unwrap().units(), -1);
+    }
+
+    #[test]
+    fn arithmetic_is_checked() {
+        let price = Price::<2>::from(100);
+        assert_eq!(
+            price.checked_add(Price::EPSILON).unwrap().to_string(),
+            "100.01"
+        );
+        assert_eq!(Price::<2>::MAX.checked_add(Price::EPSILON), None);
+        assert_eq!(Price::<2>::MIN.checked_sub(Price::EPSILON), None);
+        assert_eq!(Price::<2>::MIN.checked_neg(), None);
+        assert_eq!(price.checked_mul(3), Some(Price::from(300)));
+    }
+
+    #[test]
+    fn converts_to_and_from_ticks() {
+        let tick: Price = "0.25".parse().unwrap();
+        let price: Price = "101.25".parse().unwrap();
+        assert_eq!(price.to_ticks(tick), Some(405));
+        assert_eq!(Price::from_ticks(405, tick), Some(price));
+        assert_eq!("101.10".parse::<Price>().unwrap().to_ticks(tick), None);
+        assert_eq!(price.to_ticks(Price::ZERO), None);
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::VecDeque;
+
+use crate::{Execution, FulfillmentEngine, Order, OrderId, Price, Trade};
+
+/// How pro-rata shares that do not come out in whole units are rounded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round every share down.
+    #[default]
+    Down,
+    /// Round every share to the nearest unit, halves up. If that hands out
+    /// more than there is, the orders last in time priority give it back.
+    Nearest,
+}
+
+/// Settings for a `ProRataEngine`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProRataConfig {
+    /// Shares smaller than this are not allocated; the quantity goes to the
+    /// remainder instead.
+    pub minimum_allocation: u32,
+    pub rounding: Rounding,
+    /// Fill the order that has waited longest at the level in full before
+    /// sharing out the rest.
+    pub top_order_priority: bool,
+}
+
+/// A `FulfillmentEngine` that shares each aggressor's quantity across the
+/// orders resting at the price it trades at, in proportion to their visible
+/// size, rather than filling them first in, first out.
+///
+/// Levels are still taken best price first. What rounding and the minimum
+/// allocation leave over is filled in time priority. Each allocation yields
+/// one execution per resting order it fills, in time priority.
+pub struct ProRataEngine<'a> {
+    pub trades: &'a mut Trade,
+    pub config: ProRataConfig,
+    allocation: Option<Allocation>,
+}
+
+/// Fills still owed to resting orders by the last aggressor to be shared out.
+struct Allocation {
+    aggressor: OrderId,
+    price: Price,
+    fills: VecDeque<(OrderId, u32)>,
+}
+
+impl<'a> ProRataEngine<'a> {
+    pub fn new(trades: &'a mut Trade, config: ProRataConfig) -> Self {
+        Self {
+            trades,
+            config,
+            allocation: None,
+        }
+    }
+}
+
+impl<'a> FulfillmentEngine for ProRataEngine<'a> {
+    fn fulfill(&mut self) -> Option<Execution> {
+        let config = &self.config;
+        let allocation = &mut self.allocation;
+        self.trades.execute_trade_with(|aggressor, level| {
+            let price = level.front().unwrap().price;
+            if let Some(allocation) = allocation
+                && allocation.aggressor == aggressor.id
+                && allocation.price == price
+            {
+                // Orders may have left the level since it was shared out.
+                while let Some((resting_id, quantity)) = allocation.fills.pop_front() {
+                    if level.iter().any(|resting| resting.id == resting_id) {
+                        return (resting_id, quantity);
+                    }
+                }
+            }
+            let mut fills: VecDeque<_> = allocate(config, aggressor, level).into();
+            let fill = fills.pop_front().unwrap();
+            *allocation = Some(Allocation {
+                aggressor: aggressor.id,
+                price,
+                fills,
+            });
+            fill
+        })
+    }
+}
+
+/// Shares the aggressor's open quantity across `level`. Returns the non-zero
+/// shares in time priority; there is at least one.
+fn allocate(
+    config: &ProRataConfig,
+    aggressor: &Order,
+    level: &VecDeque<Order>,
+) -> Vec<(OrderId, u32)> {
+    let mut open: Vec<u32> = level.iter().map(|resting| resting.quantity).collect();
+    let mut shares = vec![0; level.len()];
+    let mut remaining = aggressor.quantity;
+
+    if config.top_order_priority {
+        shares[0] = remaining.min(open[0]);
+        open[0] -= shares[0];
+        remaining -= shares[0];
+    }
+
+    let total: u64 = open.iter().map(|&quantity| u64::from(quantity)).sum();
+    if total > 0 && u64::from(remaining) < total {
+        let mut pro_rata: Vec<u32> = open
+            .iter()
+            .map(|&quantity| {
+                let exact = u64::from(remaining) * u64::from(quantity);
+                let share = match config.rounding {
+                    Rounding::Down => exact / total,
+                    Rounding::Nearest => (exact + total / 2) / total,
+                } as u32;
+                if share < config.minimum_allocation {
+                    0
+                } else {
+                    share.min(quantity)
+                }
+            })
+            .collect();
+
+        let mut excess = pro_rata.iter().sum::<u32>().saturating_sub(remaining);
+        for share in pro_rata.iter_mut().rev() {
+            let returned = excess.min(*share);
+            *share -= returned;
+            excess -= returned;
+        }
+        for ((share, open), pro_rata) in shares.iter_mut().zip(&mut open).zip(pro_rata) {
+            *share += pro_rata;
+            *open -= pro_rata;
+            remaining -= pro_rata;
+        }
+    }
+
+    // Whatever is left goes first in, first out.
+    for (share, open) in shares.iter_mut().zip(open) {
+        let quantity = remaining.min(open);
+        *share += quantity;
+        remaining -= quantity;
+    }
+
+    level
+        .iter()
+        .zip(shares)
+        .filter(|(_, share)| *share > 0)
+        .map(|(resting, share)| (resting.id, share))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OrderBookEngine, Side};
+
+    fn fills(executed: &[Execution]) -> Vec<(OrderId, u32)> {
+        executed
+            .iter()
+            .map(|execution| (execution.sell_order_id, execution.quantity))
+            .collect()
+    }
+
+    #[test]
+    fn shares_fill_by_resting_size() {
+        let mut trades = Trade::new();
+        let small = trades.add_order(Side::Sell, 100, 10).unwrap();
+        let large = trades.add_order(Side::Sell, 100, 30).unwrap();
+        trades.add_order(Side::Buy, 100, 20).unwrap();
+
+        let executed = ProRataEngine::new(&mut trades, ProRataConfig::default()).fulfill_all();
+
+        assert_eq!(fills(&executed), vec![(small, 5), (large, 15)]);
+        assert!(
+            executed
+                .iter()
+                .all(|execution| execution.price == Price::from(100))
+        );
+        assert!(trades.buy_orders.is_empty());
+    }
+
+    #[test]
+    fn rounding_remainder_goes_in_time_priority() {
+        let mut trades = Trade::new();
+        let first = trades.add_order(Side::Sell, 100, 10).unwrap();
+        let second = trades.add_order(Side::Sell, 100, 10).unwrap();
+        let third = trades.add_order(Side::Sell, 100, 10).unwrap();
+        trades.add_order(Side::Buy, 100, 10).unwrap();
+
+        let executed = ProRataEngine::new(&mut trades, ProRataConfig::default()).fulfill_all();
+
+        assert_eq!(fills(&executed), vec![(first, 4), (second, 3), (third, 3)]);
+    }
+
+    #[test]
+    fn nearest_rounding_never_overallocates() {
+        let mut trades = Trade::new();
+        let first = trades.add_order(Side::Sell, 100, 10).unwrap();
+        let second = trades.add_order(Side::Sell, 100, 10).unwrap();
+        trades.add_order(Side::Buy, 100, 5).unwrap();
+
+        let config = ProRataConfig {
+            rounding: Rounding::Nearest,
+            ..ProRataConfig::default()
+        };
+        let executed = ProRataEngine::new(&mut trades, config).fulfill_all();
+
+        // both round 2.5 up to 3; the later order gives one back
+        assert_eq!(fills(&executed), vec![(first, 3), (second, 2)]);
+    }
+
+    #[test]
+    fn shares_below_the_minimum_go_to_the_remainder() {
+        let mut trades = Trade::new();
+        let small = trades.add_order(Side::Sell, 100, 2).unwrap();
+        let large = trades.add_order(Side::Sell, 100, 98).unwrap();
+        trades.add_order(Side::Buy, 100, 50).unwrap();
+
+        let config = ProRataConfig {
+            minimum_allocation: 2,
+            ..ProRataConfig::default()
+        };
+        let executed = ProRataEngine::new(&mut trades, config).fulfill_all();
+
+        // the small order's share of 1 is dropped and handed out FIFO
+        assert_eq!(fills(&executed), vec![(small, 1), (large, 49)]);
+    }
+
+    #[test]
+    fn top_order_is_filled_before_sharing() {
+        let mut trades = Trade::new();
+        let top = trades.add_order(Side::Sell, 100, 10).unwrap();
+        let second = trades.add_order(Side::Sell, 100, 10).unwrap();
+        let third = trades.add_order(Side::Sell, 100, 30).unwrap();
+        trades.add_order(Side::Buy, 100, 30).unwrap();
+
+        let config = ProRataConfig {
+            top_order_priority: true,
+            ..ProRataConfig::default()
+        };
+        let executed = ProRataEngine::new(&mut trades, config).fulfill_all();
+
+        assert_eq!(fills(&executed), vec![(top, 10), (second, 5), (third, 15)]);
+    }
+
+    #[test]
+    fn sweeps_levels_best_price_first() {
+        let mut trades = Trade::new();
+        let near = trades.add_order(Side::Sell, 100, 5).unwrap();
+        let far = trades.add_order(Side::Sell, 101, 10).unwrap();
+        trades.submit(Order::market(Side::Buy, 10)).unwrap();
+
+        let executed = ProRataEngine::new(&mut trades, ProRataConfig::default()).fulfill_all();
+
+        assert_eq!(fills(&executed), vec![(near, 5), (far, 5)]);
+        assert_eq!(executed[1].price, Price::from(101));
+    }
+
+    #[test]
+    fn same_flow_differs_from_fifo() {
+        let mut fifo = Trade::new();
+        fifo.add_order(Side::Sell, 100, 10).unwrap();
+        let later = fifo.add_order(Side::Sell, 100, 10).unwrap();
+        fifo.add_order(Side::Buy, 100, 10).unwrap();
+        let mut pro_rata = fifo.clone();
+
+        let fifo_fills = fills(&OrderBookEngine::new(&mut fifo).fulfill_all());
+        let config = ProRataConfig::default();
+        let pro_rata_fills = fills(&ProRataEngine::new(&mut pro_rata, config).fulfill_all());
+
+        assert_eq!(fifo_fills.len(), 1);
+        assert_eq!(pro_rata_fills.len(), 2);
+        assert_eq!(pro_rata_fills[1], (later, 5));
+    }
+}
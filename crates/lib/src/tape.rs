@@ -0,0 +1,146 @@
+use crate::{Execution, Price};
+
+/// Running statistics over the executions of one trading session.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SessionStats {
+    pub last: Option<Price>,
+    pub open: Option<Price>,
+    pub high: Option<Price>,
+    pub low: Option<Price>,
+    /// Total quantity traded.
+    pub volume: u64,
+    /// Sum of price times quantity over every trade. Saturates at
+    /// `Price::MAX` or `Price::MIN` rather than overflowing.
+    pub turnover: Price,
+    pub trade_count: u64,
+}
+
+impl SessionStats {
+    fn record(&mut self, execution: &Execution) {
+        let price = execution.price;
+        self.last = Some(price);
+        self.open.get_or_insert(price);
+        self.high = Some(self.high.map_or(price, |high| high.max(price)));
+        self.low = Some(self.low.map_or(price, |low| low.min(price)));
+        self.volume += u64::from(execution.quantity);
+        self.turnover = price
+            .checked_mul(i64::from(execution.quantity))
+            .and_then(|value| self.turnover.checked_add(value))
+            .unwrap_or(if price < Price::ZERO {
+                Price::MIN
+            } else {
+                Price::MAX
+            });
+        self.trade_count += 1;
+    }
+
+    /// Volume-weighted average price, rounded down to a whole unit, or
+    /// `None` before the first trade.
+    pub fn vwap(&self) -> Option<Price> {
+        let volume = i64::try_from(self.volume)
+            .ok()
+            .filter(|&volume| volume > 0)?;
+        Some(Price::from_units(self.turnover.units().div_euclid(volume)))
+    }
+}
+
+/// Time and sales for one book: every execution in the order it happened,
+/// with statistics for the current session.
+///
+/// `close` ends the session. Its prints and statistics stay readable until
+/// the first execution of the next session replaces them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tape {
+    prints: Vec<Execution>,
+    stats: SessionStats,
+    closed: bool,
+}
+
+impl Tape {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, execution: &Execution) {
+        if self.closed {
+            *self = Self::new();
+        }
+        self.prints.push(execution.clone());
+        self.stats.record(execution);
+    }
+
+    /// Ends the session; see the type docs.
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+
+    /// Executions of the session, oldest first.
+    pub fn prints(&self) -> &[Execution] {
+        &self.prints
+    }
+
+    pub fn stats(&self) -> &SessionStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Side;
+
+    fn print(price: i32, quantity: u32) -> Execution {
+        Execution {
+            buy_order_id: 1,
+            sell_order_id: 2,
+            price: Price::from(price),
+            quantity,
+            aggressor: Side::Buy,
+            sequence: 1,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn stats_track_every_print() {
+        let mut tape = Tape::new();
+        for (price, quantity) in [(100, 10), (103, 5), (98, 5), (101, 20)] {
+            tape.record(&print(price, quantity));
+        }
+
+        let stats = tape.stats();
+        assert_eq!(stats.open, Some(Price::from(100)));
+        assert_eq!(stats.high, Some(Price::from(103)));
+        assert_eq!(stats.low, Some(Price::from(98)));
+        assert_eq!(stats.last, Some(Price::from(101)));
+        assert_eq!(stats.volume, 40);
+        assert_eq!(stats.turnover, Price::from(4025));
+        assert_eq!(stats.trade_count, 4);
+        assert_eq!(stats.vwap(), Some("100.62".parse().unwrap()));
+        assert_eq!(tape.prints().len(), 4);
+    }
+
+    #[test]
+    fn closed_session_is_replaced_by_the_next_trade() {
+        let mut tape = Tape::new();
+        tape.record(&print(100, 10));
+        tape.close();
+        assert_eq!(tape.stats().trade_count, 1);
+
+        tape.record(&print(90, 1));
+        assert_eq!(tape.stats().open, Some(Price::from(90)));
+        assert_eq!(tape.stats().volume, 1);
+        assert_eq!(tape.prints().len(), 1);
+    }
+
+    #[test]
+    fn turnover_saturates() {
+        let mut tape = Tape::new();
+        tape.record(&Execution {
+            price: Price::MAX,
+            ..print(0, 2)
+        });
+        assert_eq!(tape.stats().turnover, Price::MAX);
+        assert_eq!(SessionStats::default().vwap(), None);
+    }
+}
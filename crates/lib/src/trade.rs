@@ -1,75 +1,986 @@
-use crate::{OrdersVec, order_vec};
+use std::collections::{BTreeMap, VecDeque};
 
+use crate::{
+    BookChange, BookEvent, Execution, ExpiryReason, InstrumentRules, Level1, Level2, Level3,
+    MarketDataUpdate, Order, OrderEntry, OrderId, OrderKind, OrdersVec, Peg, PegReference,
+    PostOnly, Price, Side, Tape, TimeInForce, Timestamp, Trail, order_vec,
+};
+
+/// Rule used by `Trade::execute_trade` to decide whether a buy and a sell
+/// order match.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MatchingMode {
+    /// Limit-order semantics: the best buy matches the best sell whenever the
+    /// buy price is at or above the sell price. The trade executes at the price
+    /// of the resting order, i.e. the one that was on the book first.
+    #[default]
+    Crossing,
+    /// Only a buy and a sell with exactly the same price match.
+    ExactPrice,
+}
+
+/// What the book does instead of matching two orders from the same account.
+/// The newest order is the one taking liquidity; the oldest is resting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SelfTradePrevention {
+    /// Cancel the order taking liquidity.
+    #[default]
+    CancelNewest,
+    /// Cancel the resting order.
+    CancelOldest,
+    /// Cancel both orders.
+    CancelBoth,
+    /// Take the smaller order's quantity off both orders without trading:
+    /// the smaller one is cancelled and the larger one carries on with what
+    /// is left. Orders of the same size are both cancelled.
+    DecrementAndCancel,
+}
+
+/// Whether a book matches orders as they arrive or collects them for a call
+/// auction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TradingPhase {
+    /// `Trade::execute_trade` matches orders continuously.
+    #[default]
+    Continuous,
+    /// Orders accumulate without matching until an `AuctionEngine` uncrosses
+    /// the book at a single price.
+    Auction,
+}
+
+/// The order taking liquidity in a match.
+enum Taker {
+    /// The order at the front of the pending market, IOC and FOK queue.
+    Incoming,
+    /// A resting order that crosses the other side.
+    Resting(OrderId),
+}
+
+/// An order book: the resting buy and sell orders, plus the counters used to
+/// stamp orders and executions as they happen and the events they produced.
 #[derive(Clone, Debug)]
 pub struct Trade {
     pub buy_orders: order_vec::OrdersVec,
     pub sell_orders: order_vec::OrdersVec,
+    pub matching_mode: MatchingMode,
+    pub rules: InstrumentRules,
+    pub self_trade_prevention: SelfTradePrevention,
+    pub phase: TradingPhase,
+    // Market, IOC and FOK orders waiting for the engine; they never rest on
+    // the book.
+    incoming: VecDeque<Order>,
+    // The FOK at the front of `incoming` once it has been found to fill in
+    // full, so that it is not checked again part way through.
+    fok_checked: Option<OrderId>,
+    // Stop orders waiting for their trigger, in arrival order.
+    stops: BTreeMap<OrderId, Order>,
+    events: Vec<BookEvent>,
+    market_data: Vec<MarketDataUpdate>,
+    tape: Tape,
+    last_price: Option<Price>,
+    next_order_id: OrderId,
+    next_sequence: u64,
+    next_execution: u64,
+    next_market_data: u64,
+    time: Timestamp,
 }
 
 impl Trade {
-    pub fn execute_trade(&mut self) -> Option<Trade> {
-        let mut b_index = 0;
-        let mut s_index = 0;
+    /// Enters a new limit order on the given side and returns the id assigned
+    /// to it. See `submit`.
+    pub fn add_order(
+        &mut self,
+        side: Side,
+        price: impl Into<Price>,
+        quantity: u32,
+    ) -> Result<OrderId, &'static str> {
+        self.submit(Order::new(side, price, quantity))
+    }
 
-        while b_index < self.buy_orders.len() && s_index < self.sell_orders.len() {
-            if self.buy_orders.as_slice()[b_index].price
-                < self.sell_orders.as_slice()[s_index].price
+    /// Enters a new order and returns the id assigned to it. Ids are unique
+    /// within this book and never reused. The order is also stamped with the
+    /// next sequence number, which gives it time priority behind everything
+    /// already resting at its price.
+    ///
+    /// Orders that break the book's `rules` are rejected with the rule they
+    /// broke.
+    ///
+    /// GTC, DAY and GTD limit orders rest on their side of the book; a
+    /// post-only one that would trade on entry is rejected or repriced one
+    /// tick away from the other side instead, as its `PostOnly` asks. Market,
+    /// IOC and FOK orders are queued for the engine, which fills them against
+    /// the other side and expires whatever cannot trade. Market orders are
+    /// rejected outright if there is nothing to trade against. Stop orders
+    /// wait in the trigger book until the last traded price reaches their
+    /// trigger, which may already be the case on entry. Pegged orders are
+    /// priced from the book on entry and follow it from then on.
+    pub fn submit(&mut self, mut order: Order) -> Result<OrderId, &'static str> {
+        order.id = self.next_order_id;
+        order.sequence = self.next_sequence;
+        order.entry_sequence = self.next_sequence;
+        let id = order.id;
+
+        if let TimeInForce::Gtd(expiry) = order.time_in_force
+            && expiry <= self.time
+        {
+            return Err("good-till-date expiry has already passed");
+        }
+        self.rules.check(&order)?;
+        if self.phase == TradingPhase::Auction && order.trigger().is_none() && order.is_immediate()
+        {
+            return Err("immediate orders cannot enter an auction");
+        }
+        if let Some(display_quantity) = order.display_quantity {
+            if display_quantity == 0 {
+                return Err("display quantity must be positive");
+            }
+            if order.is_immediate()
+                || !matches!(order.kind, OrderKind::Limit | OrderKind::StopLimit { .. })
             {
-                b_index += 1;
-            } else if self.buy_orders.as_slice()[b_index].price
-                > self.sell_orders.as_slice()[s_index].price
+                return Err("iceberg orders must be resting limit orders");
+            }
+        }
+        if let Some(peg) = order.peg {
+            if order.kind != OrderKind::Limit || order.is_immediate() {
+                return Err("pegged orders must be resting limit orders");
+            }
+            order.price = self
+                .peg_price(order.side, &peg)
+                .ok_or("no reference price for pegged order")?;
+            self.rules.check_price(order.price)?;
+        }
+        if let Some(post_only) = order.post_only {
+            if order.kind != OrderKind::Limit || order.is_immediate() {
+                return Err("post-only orders must be resting limit orders");
+            }
+            if self.would_trade(&order) {
+                if post_only == PostOnly::Reject {
+                    return Err("post-only order would cross the book");
+                }
+                order.price = self
+                    .post_only_price(&order)
+                    .ok_or("post-only order would cross the book")?;
+                if self.would_trade(&order) {
+                    return Err("post-only order would cross the book");
+                }
+                self.rules.check_price(order.price)?;
+            }
+        }
+        if let OrderKind::TrailingStop { trail, .. } = order.kind {
+            if matches!(trail, Trail::Amount(amount) if amount <= Price::ZERO)
+                || trail == Trail::BasisPoints(0)
             {
-                s_index += 1;
+                return Err("trailing amount must be positive");
+            }
+            let last_price = self.last_price.ok_or("no last price for trailing stop")?;
+            order.kind = OrderKind::TrailingStop {
+                trail,
+                trigger: Self::trailing_trigger(&self.rules, order.side, trail, last_price),
+            };
+        }
+        if order.trigger().is_some() {
+            self.stops.insert(id, order);
+        } else if order.is_immediate() {
+            if order.kind == OrderKind::Market && self.side(order.side.opposite()).is_empty() {
+                return Err("no liquidity for market order");
+            }
+            self.incoming.push_back(order);
+        } else {
+            self.side_mut(order.side).push(order)?;
+        }
+        self.next_order_id += 1;
+        self.next_sequence += 1;
+        self.trigger_stops();
+        self.reprice_pegs();
+        self.publish_levels();
+        Ok(id)
+    }
+
+    /// Price a pegged order on `side` should have given the book as it is,
+    /// or `None` if the book has no reference price for it.
+    fn peg_price(&self, side: Side, peg: &Peg) -> Option<Price> {
+        let own = self.side(side).best_unpegged_price();
+        let other = self.side(side.opposite()).best_unpegged_price();
+        let reference = match peg.reference {
+            PegReference::Primary => own?,
+            PegReference::Market => other?,
+            PegReference::Midpoint => {
+                let sum = own?.units().checked_add(other?.units())?;
+                Price::from_units(match side {
+                    Side::Buy => sum.div_euclid(2),
+                    Side::Sell => sum.div_euclid(2) + sum.rem_euclid(2),
+                })
+            }
+        };
+        let price = reference.checked_add(peg.offset)?;
+        let price = match (side, peg.limit) {
+            (Side::Buy, Some(limit)) => price.min(limit),
+            (Side::Sell, Some(limit)) => price.max(limit),
+            (_, None) => price,
+        };
+        self.rules.round_to_tick(side, price)
+    }
+
+    /// Moves every pegged order to the price its reference now gives it. A
+    /// pegged order whose price changes loses its time priority, the same as
+    /// an amendment would; one without a reference price stays where it is.
+    fn reprice_pegs(&mut self) {
+        if self.buy_orders.pegged().next().is_none() && self.sell_orders.pegged().next().is_none() {
+            return;
+        }
+        let mut pegged: Vec<(Side, u64, OrderId, Peg)> = self
+            .buy_orders
+            .pegged()
+            .chain(self.sell_orders.pegged())
+            .filter_map(|order| Some((order.side, order.sequence, order.id, order.peg?)))
+            .collect();
+        // Pegs moving to the same price keep their relative priority.
+        pegged.sort_unstable_by_key(|&(side, sequence, ..)| (side == Side::Sell, sequence));
+
+        for (side, _, id, peg) in pegged {
+            let Some(price) = self
+                .peg_price(side, &peg)
+                .filter(|price| self.rules.check_price(*price).is_ok())
+            else {
+                continue;
+            };
+            let order = self.side(side).get(id).unwrap();
+            if order.price == price {
+                continue;
+            }
+            let quantity = order.total_quantity();
+            let sequence = self.next_sequence;
+            self.next_sequence += 1;
+            self.side_mut(side)
+                .amend(id, price, quantity, sequence)
+                .expect("repriced peg is a valid order");
+        }
+    }
+
+    /// Trigger a trailing stop on `side` would have if the market had just
+    /// traded at `last_price`. It is rounded onto a tick away from the
+    /// market, so it is always at least a tick away.
+    fn trailing_trigger(
+        rules: &InstrumentRules,
+        side: Side,
+        trail: Trail,
+        last_price: Price,
+    ) -> Price {
+        let distance = trail.distance(last_price);
+        let (trigger, limit) = match side {
+            Side::Buy => (last_price.checked_add(distance), Price::MAX),
+            Side::Sell => (last_price.checked_sub(distance), Price::MIN),
+        };
+        // Away from the market is away from the buy side for a buy stop.
+        trigger
+            .and_then(|trigger| rules.round_to_tick(side.opposite(), trigger))
+            .unwrap_or(limit)
+    }
+
+    /// Moves trailing stop triggers after the last traded price changes. A
+    /// trigger only ever moves towards the market: up for a sell, down for a
+    /// buy.
+    fn trail_stops(&mut self) {
+        let Some(last_price) = self.last_price else {
+            return;
+        };
+        for order in self.stops.values_mut() {
+            if let OrderKind::TrailingStop { trail, trigger } = order.kind {
+                let candidate = Self::trailing_trigger(&self.rules, order.side, trail, last_price);
+                let trigger = match order.side {
+                    Side::Buy => trigger.min(candidate),
+                    Side::Sell => trigger.max(candidate),
+                };
+                order.kind = OrderKind::TrailingStop { trail, trigger };
+            }
+        }
+    }
+
+    /// Moves every stop order whose trigger the last traded price has reached
+    /// into matching. Stops activate in arrival order; any they trigger in
+    /// turn activate after them, once their own fills have set a new price.
+    fn trigger_stops(&mut self) {
+        let Some(last_price) = self.last_price else {
+            return;
+        };
+        let triggered: Vec<OrderId> = self
+            .stops
+            .values()
+            .filter(|order| match (order.side, order.trigger()) {
+                (Side::Buy, Some(trigger)) => last_price >= trigger,
+                (Side::Sell, Some(trigger)) => last_price <= trigger,
+                (_, None) => false,
+            })
+            .map(|order| order.id)
+            .collect();
+
+        for id in triggered {
+            let mut order = self.stops.remove(&id).unwrap();
+            if let OrderKind::Stop { .. } | OrderKind::TrailingStop { .. } = order.kind {
+                order.kind = OrderKind::Market;
+                order.time_in_force = TimeInForce::Ioc;
+            } else {
+                order.kind = OrderKind::Limit;
+            }
+            order.sequence = self.next_sequence;
+            order.entry_sequence = self.next_sequence;
+            self.next_sequence += 1;
+            self.events.push(BookEvent::Triggered {
+                order: order.clone(),
+            });
+
+            if order.is_immediate() {
+                self.incoming.push_back(order);
             } else {
-                let mut to_execute = Trade::new();
-
-                to_execute
-                    .buy_orders
-                    .push(self.buy_orders.remove(b_index).unwrap())
-                    .unwrap();
-                to_execute
-                    .sell_orders
-                    .push(self.sell_orders.remove(s_index).unwrap())
-                    .unwrap();
-
-                return Some(to_execute);
+                self.side_mut(order.side)
+                    .push(order)
+                    .expect("stop-limit orders are validated on submit");
+            }
+        }
+    }
+
+    /// Removes the order with the given id from whichever side holds it.
+    pub fn cancel(&mut self, id: OrderId) -> Option<Order> {
+        if let Some(index) = self.incoming.iter().position(|o| o.id == id) {
+            return self.incoming.remove(index);
+        }
+        if let Some(order) = self.stops.remove(&id) {
+            return Some(order);
+        }
+        let order = self
+            .buy_orders
+            .cancel(id)
+            .or_else(|| self.sell_orders.cancel(id))?;
+        self.reprice_pegs();
+        self.publish_levels();
+        Some(order)
+    }
+
+    /// Amends a resting order to a new price and total quantity; see
+    /// `OrdersVec::amend` for the priority rules.
+    /// The price of a pegged order comes from the book and cannot be amended.
+    pub fn amend(
+        &mut self,
+        id: OrderId,
+        new_price: impl Into<Price>,
+        new_quantity: u32,
+    ) -> Result<(), &'static str> {
+        let new_price = new_price.into();
+        // Only used if the amendment loses priority, but always consumed so
+        // that sequence numbers never go backwards.
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        if let Some(order) = self.get(id)
+            && order.peg.is_some()
+            && order.price != new_price
+        {
+            return Err("cannot change the price of a pegged order");
+        }
+        if let Some(order) = self.get(id) {
+            if order.peg.is_none() {
+                self.rules.check_price(new_price)?;
+            }
+            self.rules.check_quantity(new_quantity)?;
+        }
+        if let Some(order) = self.get(id)
+            && order.post_only.is_some()
+            && self.would_trade(&Order {
+                price: new_price,
+                ..order.clone()
+            })
+        {
+            return Err("post-only order would cross the book");
+        }
+
+        if self.buy_orders.get(id).is_some() {
+            self.buy_orders
+                .amend(id, new_price, new_quantity, sequence)?;
+        } else {
+            self.sell_orders
+                .amend(id, new_price, new_quantity, sequence)?;
+        }
+        self.reprice_pegs();
+        self.publish_levels();
+        Ok(())
+    }
+
+    /// Sets the book's clock. Executions are stamped with this time, so the
+    /// caller decides where time comes from (wall clock, replay, tests).
+    /// Good-till-date orders whose expiry has been reached are expired.
+    pub fn set_time(&mut self, time: Timestamp) {
+        self.time = time;
+        self.expire_resting(
+            ExpiryReason::GoodTillDate,
+            |order| matches!(order.time_in_force, TimeInForce::Gtd(expiry) if expiry <= time),
+        );
+    }
+
+    /// Closes the trading session: every resting day order is expired and
+    /// the tape is closed.
+    pub fn end_session(&mut self) {
+        self.tape.close();
+        self.expire_resting(ExpiryReason::EndOfDay, |order| {
+            order.time_in_force == TimeInForce::Day
+        });
+    }
+
+    /// Returns the events recorded since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<BookEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Returns the market data updates published since the last call, in
+    /// sequence. Every entry, cancel, amendment and match that changes a
+    /// price level publishes the level's new state; every execution
+    /// publishes a print.
+    pub fn take_market_data(&mut self) -> Vec<MarketDataUpdate> {
+        // Picks up changes made directly through `buy_orders` or
+        // `sell_orders`.
+        self.publish_levels();
+        std::mem::take(&mut self.market_data)
+    }
+
+    fn publish(&mut self, change: BookChange) {
+        self.market_data.push(MarketDataUpdate {
+            sequence: self.next_market_data,
+            change,
+        });
+        self.next_market_data += 1;
+    }
+
+    /// Publishes the levels changed since they were last published, bids
+    /// first, each side in ascending price.
+    fn publish_levels(&mut self) {
+        for side in [Side::Buy, Side::Sell] {
+            for change in self.side_mut(side).take_changes() {
+                match change {
+                    (None, Some(level)) => self.publish(BookChange::LevelAdded { side, level }),
+                    (Some(_), Some(level)) => {
+                        self.publish(BookChange::LevelChanged { side, level })
+                    }
+                    (Some(level), None) => self.publish(BookChange::LevelRemoved {
+                        side,
+                        price: level.price,
+                    }),
+                    (None, None) => {}
+                }
+            }
+        }
+    }
+
+    fn expire_resting(&mut self, reason: ExpiryReason, expires: impl Fn(&Order) -> bool) {
+        let ids: Vec<OrderId> = self
+            .buy_orders
+            .iter()
+            .chain(self.sell_orders.iter())
+            .chain(self.stops.values())
+            .filter(|order| expires(order))
+            .map(|order| order.id)
+            .collect();
+        for id in ids {
+            if let Some(order) = self.cancel(id) {
+                self.events.push(BookEvent::Expired { order, reason });
+            }
+        }
+    }
+
+    pub fn time(&self) -> Timestamp {
+        self.time
+    }
+
+    /// Price of the most recent execution, if there has been one.
+    pub fn last_price(&self) -> Option<Price> {
+        self.last_price
+    }
+
+    /// Executions on this book with statistics for the current session.
+    pub fn tape(&self) -> &Tape {
+        &self.tape
+    }
+
+    /// Stop orders that have not been triggered yet, in arrival order.
+    pub fn stop_orders(&self) -> impl Iterator<Item = &Order> {
+        self.stops.values()
+    }
+
+    pub fn get(&self, id: OrderId) -> Option<&Order> {
+        self.buy_orders.get(id).or_else(|| self.sell_orders.get(id))
+    }
+
+    /// Best bid and offer with their visible sizes.
+    pub fn level1(&self) -> Level1 {
+        Level1 {
+            bid: self.buy_orders.top(),
+            offer: self.sell_orders.top(),
+        }
+    }
+
+    /// Visible quantity by price for the best `depth` levels on each side.
+    pub fn level2(&self, depth: usize) -> Level2 {
+        Level2 {
+            bids: self.buy_orders.depth(depth),
+            offers: self.sell_orders.depth(depth),
+        }
+    }
+
+    /// Every resting order with its visible quantity. Stop orders are not
+    /// shown until they trigger.
+    pub fn level3(&self) -> Level3 {
+        Level3 {
+            bids: self.buy_orders.iter().map(OrderEntry::from).collect(),
+            offers: self.sell_orders.iter().map(OrderEntry::from).collect(),
+        }
+    }
+
+    fn side(&self, side: Side) -> &OrdersVec {
+        match side {
+            Side::Buy => &self.buy_orders,
+            Side::Sell => &self.sell_orders,
+        }
+    }
+
+    fn side_mut(&mut self, side: Side) -> &mut OrdersVec {
+        match side {
+            Side::Buy => &mut self.buy_orders,
+            Side::Sell => &mut self.sell_orders,
+        }
+    }
+
+    /// Executes at most one match. Pending market, IOC and FOK orders are
+    /// served first, in arrival order, at the prices resting on the other
+    /// side; after that resting orders match according to `matching_mode`.
+    /// Within a price level the order that has waited longest is filled
+    /// first. Whatever is left of a resting order stays on the book. The
+    /// execution sets the last traded price, which moves trailing stops and
+    /// may trigger stop orders. Nothing matches during an auction phase.
+    pub fn execute_trade(&mut self) -> Option<Execution> {
+        self.execute_trade_with(|aggressor, level| {
+            let resting = level.front().unwrap();
+            (resting.id, aggressor.quantity.min(resting.quantity))
+        })
+    }
+
+    /// Like `execute_trade`, but `allocate` decides which resting order the
+    /// aggressor trades against. It is given the aggressor and the orders
+    /// resting at the level it trades at, in time priority, and returns the
+    /// id of one of those orders with the quantity to fill. The quantity is
+    /// kept between one and what both orders have open.
+    ///
+    /// If the chosen order belongs to the aggressor's account the two do not
+    /// trade; `self_trade_prevention` is applied instead and matching carries
+    /// on with the next match.
+    pub fn execute_trade_with(
+        &mut self,
+        mut allocate: impl FnMut(&Order, &VecDeque<Order>) -> (OrderId, u32),
+    ) -> Option<Execution> {
+        if self.phase == TradingPhase::Auction {
+            return None;
+        }
+        loop {
+            let (taker, side, price) = self.next_match()?;
+            let aggressor = match taker {
+                Taker::Incoming => self.incoming.front().unwrap(),
+                Taker::Resting(id) => self.side(side).get(id).unwrap(),
+            };
+            let level = self.side(side.opposite()).level(price).unwrap();
+            let (resting_id, quantity) = allocate(aggressor, level);
+            let resting = level
+                .iter()
+                .find(|resting| resting.id == resting_id)
+                .expect("allocated order must rest at the matched level");
+            let quantity = quantity.clamp(1, aggressor.quantity.min(resting.quantity));
+            let aggressor_id = aggressor.id;
+
+            if is_self_trade(aggressor, resting) {
+                self.prevent_self_trade(side, aggressor_id, resting_id);
+                continue;
+            }
+
+            self.fill_resting(side.opposite(), resting_id, quantity);
+            match taker {
+                Taker::Incoming => {
+                    let incoming = self.incoming.front_mut().unwrap();
+                    incoming.quantity -= quantity;
+                    if incoming.quantity == 0 {
+                        self.incoming.pop_front();
+                    }
+                }
+                Taker::Resting(id) => self.fill_resting(side, id, quantity),
+            }
+
+            let execution = match side {
+                Side::Buy => self.record_execution(aggressor_id, resting_id, price, quantity, side),
+                Side::Sell => {
+                    self.record_execution(resting_id, aggressor_id, price, quantity, side)
+                }
+            };
+            self.after_execution(&execution);
+            return Some(execution);
+        }
+    }
+
+    /// Matches the best bid against the best offer at `price`, provided both
+    /// are willing to trade there. Auctions use this to execute every match
+    /// at the uncrossing price, whatever the phase. Self-trades are
+    /// prevented as in `execute_trade_with`.
+    pub fn execute_at(&mut self, price: impl Into<Price>) -> Option<Execution> {
+        let price = price.into();
+        loop {
+            let best_bid = self.buy_orders.best()?;
+            let best_ask = self.sell_orders.best()?;
+            if best_bid.price < price || best_ask.price > price {
+                return None;
+            }
+            if is_self_trade(best_bid, best_ask) {
+                let (buy_id, sell_id) = (best_bid.id, best_ask.id);
+                if best_bid.entry_sequence > best_ask.entry_sequence {
+                    self.prevent_self_trade(Side::Buy, buy_id, sell_id);
+                } else {
+                    self.prevent_self_trade(Side::Sell, sell_id, buy_id);
+                }
+                continue;
+            }
+            let execution = self.take_match(best_bid.id, best_ask.id, price);
+            self.after_execution(&execution);
+            return Some(execution);
+        }
+    }
+
+    /// Applies `self_trade_prevention` to an aggressor on `side` and the
+    /// resting order from the same account it would have traded with.
+    fn prevent_self_trade(&mut self, side: Side, aggressor_id: OrderId, resting_id: OrderId) {
+        let (cancel_aggressor, cancel_resting) = match self.self_trade_prevention {
+            SelfTradePrevention::CancelNewest => (true, false),
+            SelfTradePrevention::CancelOldest => (false, true),
+            SelfTradePrevention::CancelBoth => (true, true),
+            SelfTradePrevention::DecrementAndCancel => {
+                let aggressor = self.working(aggressor_id).unwrap().total_quantity();
+                let resting = self.working(resting_id).unwrap().total_quantity();
+                let quantity = aggressor.min(resting);
+                if aggressor > quantity {
+                    self.decrement(side, aggressor_id, quantity);
+                }
+                if resting > quantity {
+                    self.decrement(side.opposite(), resting_id, quantity);
+                }
+                (aggressor == quantity, resting == quantity)
+            }
+        };
+
+        for (cancel, id) in [
+            (cancel_aggressor, aggressor_id),
+            (cancel_resting, resting_id),
+        ] {
+            if cancel && let Some(order) = self.cancel(id) {
+                self.events.push(BookEvent::Expired {
+                    order,
+                    reason: ExpiryReason::SelfTrade,
+                });
+            }
+        }
+    }
+
+    /// A resting or pending order by id.
+    fn working(&self, id: OrderId) -> Option<&Order> {
+        self.incoming
+            .iter()
+            .find(|order| order.id == id)
+            .or_else(|| self.get(id))
+    }
+
+    /// Takes `quantity` off an order without trading it, less than the order
+    /// has open in total. An iceberg shows its next slice as it would after
+    /// a fill.
+    fn decrement(&mut self, side: Side, id: OrderId, mut quantity: u32) {
+        if let Some(order) = self.incoming.iter_mut().find(|order| order.id == id) {
+            order.quantity -= quantity;
+            return;
+        }
+        while quantity > 0 {
+            let visible = self.side(side).get(id).unwrap().quantity.min(quantity);
+            self.fill_resting(side, id, visible);
+            quantity -= visible;
+        }
+    }
+
+    fn after_execution(&mut self, execution: &Execution) {
+        self.last_price = Some(execution.price);
+        self.tape.record(execution);
+        self.trail_stops();
+        self.trigger_stops();
+        self.reprice_pegs();
+        self.publish_levels();
+    }
+
+    /// Finds the order that takes liquidity in the next match, its side and
+    /// the price of the level it trades against. Pending orders that can no
+    /// longer trade are expired along the way.
+    fn next_match(&mut self) -> Option<(Taker, Side, Price)> {
+        if let Some(found) = self.next_incoming_match() {
+            return Some(found);
+        }
+        let (best_bid, best_ask) = match self.matching_mode {
+            MatchingMode::Crossing => {
+                let best_bid = self.buy_orders.best()?;
+                let best_ask = self.sell_orders.best()?;
+                if best_bid.price < best_ask.price {
+                    return None;
+                }
+                (best_bid, best_ask)
+            }
+            // Walk buy levels best-first and take the first one with a sell
+            // level at the same price.
+            MatchingMode::ExactPrice => self.buy_orders.levels().find_map(|(price, buys)| {
+                Some((buys.front()?, self.sell_orders.level(price)?.front()?))
+            })?,
+        };
+
+        // Whichever order entered the book first was resting, and the trade
+        // happens at its price.
+        Some(if best_bid.entry_sequence < best_ask.entry_sequence {
+            (Taker::Resting(best_ask.id), Side::Sell, best_bid.price)
+        } else {
+            (Taker::Resting(best_bid.id), Side::Buy, best_ask.price)
+        })
+    }
+
+    fn next_incoming_match(&mut self) -> Option<(Taker, Side, Price)> {
+        loop {
+            // A FOK is checked once, when it reaches the front, so it is
+            // never killed after it has started to fill.
+            let incoming = self.incoming.front()?;
+            if incoming.time_in_force == TimeInForce::Fok
+                && self.fok_checked != Some(incoming.id)
+                && self.can_fill(incoming)
+            {
+                self.fok_checked = Some(incoming.id);
+            }
+
+            let incoming = self.incoming.front()?;
+            let killed =
+                incoming.time_in_force == TimeInForce::Fok && self.fok_checked != Some(incoming.id);
+            let resting = self
+                .side(incoming.side.opposite())
+                .best()
+                .filter(|resting| self.accepts(incoming, resting.price));
+
+            let Some(resting) = resting.filter(|_| !killed) else {
+                // Nothing (more) it can trade against: expire the remainder.
+                let order = self.incoming.pop_front().unwrap();
+                self.expire_immediate(order);
+                continue;
+            };
+            return Some((Taker::Incoming, incoming.side, resting.price));
+        }
+    }
+
+    /// Expires every market, IOC and FOK order still waiting to match. An
+    /// auction does this before it uncrosses, so none of them outlives it.
+    pub(crate) fn expire_incoming(&mut self) {
+        while let Some(order) = self.incoming.pop_front() {
+            self.expire_immediate(order);
+        }
+    }
+
+    fn expire_immediate(&mut self, order: Order) {
+        let reason = if order.time_in_force == TimeInForce::Fok {
+            ExpiryReason::FillOrKill
+        } else {
+            ExpiryReason::ImmediateOrCancel
+        };
+        self.events.push(BookEvent::Expired { order, reason });
+    }
+
+    /// Whether `order` may trade against an order resting at `price`.
+    fn accepts(&self, order: &Order, price: Price) -> bool {
+        match (order.kind, self.matching_mode, order.side) {
+            (OrderKind::Market, _, _) => true,
+            (OrderKind::Limit, MatchingMode::ExactPrice, _) => order.price == price,
+            (OrderKind::Limit, MatchingMode::Crossing, Side::Buy) => order.price >= price,
+            (OrderKind::Limit, MatchingMode::Crossing, Side::Sell) => order.price <= price,
+            // Stops are converted to market or limit orders when triggered.
+            (
+                OrderKind::Stop { .. }
+                | OrderKind::StopLimit { .. }
+                | OrderKind::TrailingStop { .. },
+                _,
+                _,
+            ) => false,
+        }
+    }
+
+    /// Whether a limit `order` entering the book now would trade straight away.
+    fn would_trade(&self, order: &Order) -> bool {
+        let opposite = self.side(order.side.opposite());
+        match self.matching_mode {
+            MatchingMode::Crossing => opposite
+                .best_price()
+                .is_some_and(|price| self.accepts(order, price)),
+            MatchingMode::ExactPrice => opposite.level(order.price).is_some(),
+        }
+    }
+
+    /// Price one tick away from where a post-only `order` would trade, or
+    /// `None` if that is out of range.
+    fn post_only_price(&self, order: &Order) -> Option<Price> {
+        let touch = match self.matching_mode {
+            MatchingMode::Crossing => self.side(order.side.opposite()).best_price(),
+            MatchingMode::ExactPrice => None,
+        }
+        .unwrap_or(order.price);
+        match order.side {
+            Side::Buy => touch.checked_sub(self.rules.tick_size),
+            Side::Sell => touch.checked_add(self.rules.tick_size),
+        }
+    }
+
+    /// Whether enough acceptable quantity rests on the other side to fill
+    /// `order` in full, taken in priority. Orders from its own account are
+    /// passed over if self-trade prevention cancels them; otherwise reaching
+    /// one first means `order` would be cancelled or cut short there.
+    fn can_fill(&self, order: &Order) -> bool {
+        let needed = u64::from(order.quantity);
+        let mut available = 0;
+        for resting in self
+            .side(order.side.opposite())
+            .levels()
+            .filter(|(price, _)| self.accepts(order, *price))
+            .flat_map(|(_, level)| level.iter())
+        {
+            if available >= needed {
+                break;
             }
+            if is_self_trade(order, resting) {
+                if self.self_trade_prevention == SelfTradePrevention::CancelOldest {
+                    continue;
+                }
+                return false;
+            }
+            available += u64::from(resting.total_quantity());
+        }
+        available >= needed
+    }
+
+    /// Fills the given buy and sell against each other at `price`.
+    fn take_match(&mut self, buy_id: OrderId, sell_id: OrderId, price: Price) -> Execution {
+        let buy = self.buy_orders.get(buy_id).unwrap();
+        let sell = self.sell_orders.get(sell_id).unwrap();
+        let quantity = buy.quantity.min(sell.quantity);
+        let aggressor = if buy.entry_sequence > sell.entry_sequence {
+            Side::Buy
+        } else {
+            Side::Sell
+        };
+
+        self.fill_resting(Side::Buy, buy_id, quantity);
+        self.fill_resting(Side::Sell, sell_id, quantity);
+
+        self.record_execution(buy_id, sell_id, price, quantity, aggressor)
+    }
+
+    /// Fills an order on the book. When that uses up the visible quantity of
+    /// an iceberg, the next slice is shown and queued behind everything else
+    /// at its price.
+    fn fill_resting(&mut self, side: Side, id: OrderId, quantity: u32) {
+        let order = self.side(side).get(id).unwrap();
+        if quantity < order.quantity || order.hidden_quantity == 0 {
+            self.side_mut(side).fill(id, quantity);
+            return;
         }
 
-        None
+        let mut order = self.side_mut(side).cancel(id).unwrap();
+        order.quantity -= quantity;
+        order.replenish();
+        order.sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.side_mut(side)
+            .push(order)
+            .expect("a replenished iceberg is still a valid order");
+    }
+
+    fn record_execution(
+        &mut self,
+        buy_id: OrderId,
+        sell_id: OrderId,
+        price: Price,
+        quantity: u32,
+        aggressor: Side,
+    ) -> Execution {
+        let sequence = self.next_execution;
+        self.next_execution += 1;
+        self.publish_levels();
+        self.publish(BookChange::TradePrinted {
+            price,
+            quantity,
+            aggressor,
+            timestamp: self.time,
+        });
+
+        Execution {
+            buy_order_id: buy_id,
+            sell_order_id: sell_id,
+            price,
+            quantity,
+            aggressor,
+            sequence,
+            timestamp: self.time,
+        }
     }
 
     pub fn new() -> Self {
+        Self::with_matching_mode(MatchingMode::default())
+    }
+
+    pub fn with_matching_mode(matching_mode: MatchingMode) -> Self {
         Self {
-            buy_orders: OrdersVec::new(crate::OrderType::Buy),
-            sell_orders: OrdersVec::new(crate::OrderType::Sell),
+            buy_orders: OrdersVec::new(Side::Buy),
+            sell_orders: OrdersVec::new(Side::Sell),
+            matching_mode,
+            rules: InstrumentRules::default(),
+            self_trade_prevention: SelfTradePrevention::default(),
+            phase: TradingPhase::default(),
+            incoming: VecDeque::new(),
+            fok_checked: None,
+            stops: BTreeMap::new(),
+            events: Vec::new(),
+            market_data: Vec::new(),
+            tape: Tape::new(),
+            last_price: None,
+            next_order_id: 1,
+            next_sequence: 1,
+            next_execution: 1,
+            next_market_data: 1,
+            time: 0,
         }
     }
 }
 
+/// Whether two orders on opposite sides belong to the same account.
+fn is_self_trade(a: &Order, b: &Order) -> bool {
+    a.account.is_some() && a.account == b.account
+}
+
+impl Default for Trade {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Order, OrderType};
+
+    fn price(s: &str) -> Price {
+        s.parse().unwrap()
+    }
 
     #[test]
     fn execute_trade_returns_none_when_no_match() {
         let mut trades = Trade::new();
 
-        trades
-            .buy_orders
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 100,
-            })
-            .unwrap();
-        trades
-            .sell_orders
-            .push(Order {
-                order_type: OrderType::Sell,
-                price: 200,
-            })
-            .unwrap();
+        trades.add_order(Side::Buy, 100, 10).unwrap();
+        trades.add_order(Side::Sell, 200, 10).unwrap();
 
         let result = trades.execute_trade();
         assert!(result.is_none());
@@ -81,29 +992,15 @@ mod tests {
     fn execute_trade_returns_trade_and_removes_orders() {
         let mut trades = Trade::new();
 
-        trades
-            .buy_orders
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 50,
-            })
-            .unwrap();
-        trades
-            .sell_orders
-            .push(Order {
-                order_type: OrderType::Sell,
-                price: 50,
-            })
-            .unwrap();
+        trades.add_order(Side::Buy, 50, 10).unwrap();
+        trades.add_order(Side::Sell, 50, 10).unwrap();
 
         let result = trades.execute_trade();
         assert!(result.is_some());
 
         let executed = result.unwrap();
-        assert_eq!(executed.buy_orders.len(), 1);
-        assert_eq!(executed.sell_orders.len(), 1);
-        assert_eq!(executed.buy_orders.as_slice()[0].price, 50);
-        assert_eq!(executed.sell_orders.as_slice()[0].price, 50);
+        assert_eq!(executed.price, Price::from(50));
+        assert_eq!(executed.quantity, 10);
 
         // original orders removed
         assert_eq!(trades.buy_orders.len(), 0);
@@ -112,37 +1009,13 @@ mod tests {
 
     #[test]
     fn execute_trade_removes_matching_middle_orders() {
-        let mut trades = Trade::new();
+        let mut trades = Trade::with_matching_mode(MatchingMode::ExactPrice);
 
-        trades
-            .buy_orders
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 30,
-            })
-            .unwrap();
-        trades
-            .sell_orders
-            .push(Order {
-                order_type: OrderType::Sell,
-                price: 30,
-            })
-            .unwrap();
+        trades.add_order(Side::Buy, 30, 10).unwrap();
+        trades.add_order(Side::Sell, 30, 10).unwrap();
 
-        trades
-            .buy_orders
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 50,
-            })
-            .unwrap();
-        trades
-            .sell_orders
-            .push(Order {
-                order_type: OrderType::Sell,
-                price: 60,
-            })
-            .unwrap();
+        trades.add_order(Side::Buy, 50, 10).unwrap();
+        trades.add_order(Side::Sell, 60, 10).unwrap();
 
         let result = trades.execute_trade();
         assert!(result.is_some());
@@ -150,8 +1023,8 @@ mod tests {
         // after execution, remaining orders should be the middle ones
         assert_eq!(trades.buy_orders.len(), 1);
         assert_eq!(trades.sell_orders.len(), 1);
-        assert_eq!(trades.buy_orders.as_slice()[0].price, 50);
-        assert_eq!(trades.sell_orders.as_slice()[0].price, 60);
+        assert_eq!(trades.buy_orders.best().unwrap().price, Price::from(50));
+        assert_eq!(trades.sell_orders.best().unwrap().price, Price::from(60));
     }
 
     #[test]
@@ -165,35 +1038,11 @@ mod tests {
     fn execute_trade_with_multiple_equal_prices_removes_one_pair() {
         let mut trades = Trade::new();
 
-        trades
-            .buy_orders
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 50,
-            })
-            .unwrap();
-        trades
-            .buy_orders
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 50,
-            })
-            .unwrap();
+        trades.add_order(Side::Buy, 50, 10).unwrap();
+        trades.add_order(Side::Buy, 50, 10).unwrap();
 
-        trades
-            .sell_orders
-            .push(Order {
-                order_type: OrderType::Sell,
-                price: 50,
-            })
-            .unwrap();
-        trades
-            .sell_orders
-            .push(Order {
-                order_type: OrderType::Sell,
-                price: 50,
-            })
-            .unwrap();
+        trades.add_order(Side::Sell, 50, 10).unwrap();
+        trades.add_order(Side::Sell, 50, 10).unwrap();
 
         let result = trades.execute_trade();
         assert!(result.is_some());
@@ -201,57 +1050,21 @@ mod tests {
         // one pair executed, one remaining on each side
         assert_eq!(trades.buy_orders.len(), 1);
         assert_eq!(trades.sell_orders.len(), 1);
-        assert_eq!(trades.buy_orders.as_slice()[0].price, 50);
-        assert_eq!(trades.sell_orders.as_slice()[0].price, 50);
+        assert_eq!(trades.buy_orders.best().unwrap().price, Price::from(50));
+        assert_eq!(trades.sell_orders.best().unwrap().price, Price::from(50));
     }
 
     #[test]
     fn execute_trade_matches_late_indices() {
-        let mut trades = Trade::new();
+        let mut trades = Trade::with_matching_mode(MatchingMode::ExactPrice);
 
-        trades
-            .buy_orders
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 10,
-            })
-            .unwrap();
-        trades
-            .buy_orders
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 20,
-            })
-            .unwrap();
-        trades
-            .buy_orders
-            .push(Order {
-                order_type: OrderType::Buy,
-                price: 50,
-            })
-            .unwrap();
+        trades.add_order(Side::Buy, 10, 10).unwrap();
+        trades.add_order(Side::Buy, 20, 10).unwrap();
+        trades.add_order(Side::Buy, 50, 10).unwrap();
 
-        trades
-            .sell_orders
-            .push(Order {
-                order_type: OrderType::Sell,
-                price: 5,
-            })
-            .unwrap();
-        trades
-            .sell_orders
-            .push(Order {
-                order_type: OrderType::Sell,
-                price: 25,
-            })
-            .unwrap();
-        trades
-            .sell_orders
-            .push(Order {
-                order_type: OrderType::Sell,
-                price: 50,
-            })
-            .unwrap();
+        trades.add_order(Side::Sell, 5, 10).unwrap();
+        trades.add_order(Side::Sell, 25, 10).unwrap();
+        trades.add_order(Side::Sell, 50, 10).unwrap();
 
         let result = trades.execute_trade();
         assert!(result.is_some());
@@ -259,9 +1072,1122 @@ mod tests {
         // matched at price 50; remaining orders are the earlier ones
         assert_eq!(trades.buy_orders.len(), 2);
         assert_eq!(trades.sell_orders.len(), 2);
-        assert_eq!(trades.buy_orders.as_slice()[0].price, 10);
-        assert_eq!(trades.buy_orders.as_slice()[1].price, 20);
-        assert_eq!(trades.sell_orders.as_slice()[0].price, 5);
-        assert_eq!(trades.sell_orders.as_slice()[1].price, 25);
+        assert_eq!(trades.buy_orders.best().unwrap().price, Price::from(20));
+        assert_eq!(
+            trades.buy_orders.iter().nth(1).unwrap().price,
+            Price::from(10)
+        );
+        assert_eq!(trades.sell_orders.best().unwrap().price, Price::from(5));
+        assert_eq!(
+            trades.sell_orders.iter().nth(1).unwrap().price,
+            Price::from(25)
+        );
+    }
+
+    #[test]
+    fn execute_trade_matches_crossing_prices_at_resting_price() {
+        let mut trades = Trade::new();
+
+        trades.add_order(Side::Sell, 50, 10).unwrap();
+        trades.add_order(Side::Buy, 100, 10).unwrap();
+
+        let executed = trades.execute_trade().unwrap();
+        // the sell was resting, so the buy pays the sell's price
+        assert_eq!(executed.price, Price::from(50));
+        assert_eq!(executed.aggressor, Side::Buy);
+        assert!(trades.buy_orders.is_empty());
+        assert!(trades.sell_orders.is_empty());
+    }
+
+    #[test]
+    fn execute_trade_incoming_sell_gets_resting_buy_price() {
+        let mut trades = Trade::new();
+
+        trades.add_order(Side::Buy, 100, 10).unwrap();
+        trades.add_order(Side::Sell, 50, 10).unwrap();
+
+        let executed = trades.execute_trade().unwrap();
+        assert_eq!(executed.price, Price::from(100));
+        assert_eq!(executed.aggressor, Side::Sell);
+    }
+
+    #[test]
+    fn execute_trade_crossing_matches_best_prices() {
+        let mut trades = Trade::new();
+
+        for price in [30, 50] {
+            trades.add_order(Side::Buy, price, 10).unwrap();
+        }
+        for price in [40, 60] {
+            trades.add_order(Side::Sell, price, 10).unwrap();
+        }
+
+        // buy 50 was resting when sell 40 arrived
+        let executed = trades.execute_trade().unwrap();
+        assert_eq!(executed.price, Price::from(50));
+
+        // best bid 30 is now below best ask 60
+        assert!(trades.execute_trade().is_none());
+        assert_eq!(trades.buy_orders.best().unwrap().price, Price::from(30));
+        assert_eq!(trades.sell_orders.best().unwrap().price, Price::from(60));
+    }
+
+    #[test]
+    fn execute_trade_exact_price_ignores_crossing_prices() {
+        let mut trades = Trade::with_matching_mode(MatchingMode::ExactPrice);
+
+        trades.add_order(Side::Buy, 100, 10).unwrap();
+        trades.add_order(Side::Sell, 50, 10).unwrap();
+
+        assert!(trades.execute_trade().is_none());
+        assert_eq!(trades.buy_orders.len(), 1);
+        assert_eq!(trades.sell_orders.len(), 1);
+    }
+
+    #[test]
+    fn execute_trade_partial_fill_leaves_remainder_resting() {
+        let mut trades = Trade::new();
+
+        trades.add_order(Side::Sell, 50, 30).unwrap();
+        trades.add_order(Side::Buy, 55, 10).unwrap();
+
+        let executed = trades.execute_trade().unwrap();
+        assert_eq!(executed.quantity, 10);
+        assert_eq!(executed.price, Price::from(50));
+
+        assert!(trades.buy_orders.is_empty());
+        assert_eq!(trades.sell_orders.len(), 1);
+        assert_eq!(trades.sell_orders.best().unwrap().quantity, 20);
+    }
+
+    #[test]
+    fn execute_trade_large_buy_sweeps_sells_one_fill_at_a_time() {
+        let mut trades = Trade::new();
+
+        trades.add_order(Side::Sell, 50, 5).unwrap();
+        trades.add_order(Side::Sell, 51, 5).unwrap();
+        trades.add_order(Side::Buy, 60, 12).unwrap();
+
+        let first = trades.execute_trade().unwrap();
+        assert_eq!(first.price, Price::from(50));
+        assert_eq!(first.quantity, 5);
+
+        let second = trades.execute_trade().unwrap();
+        assert_eq!(second.price, Price::from(51));
+        assert_eq!(second.quantity, 5);
+        assert_eq!(second.buy_order_id, first.buy_order_id);
+        assert_eq!(second.sequence, first.sequence + 1);
+
+        assert!(trades.execute_trade().is_none());
+        assert!(trades.sell_orders.is_empty());
+        assert_eq!(trades.buy_orders.best().unwrap().quantity, 2);
+    }
+
+    #[test]
+    fn add_order_assigns_increasing_ids_across_sides() {
+        let mut trades = Trade::new();
+
+        let first = trades.add_order(Side::Buy, 100, 10).unwrap();
+        let second = trades.add_order(Side::Sell, 200, 10).unwrap();
+        let third = trades.add_order(Side::Buy, 90, 10).unwrap();
+
+        assert!(first < second && second < third);
+        assert_eq!(trades.get(second).unwrap().side, Side::Sell);
+    }
+
+    #[test]
+    fn add_order_rejection_does_not_use_an_id() {
+        let mut trades = Trade::new();
+
+        assert!(trades.add_order(Side::Buy, 0, 10).is_err());
+        let id = trades.add_order(Side::Buy, 100, 10).unwrap();
+        assert_eq!(id, 1);
+    }
+
+    #[test]
+    fn cancel_removes_order_from_either_side() {
+        let mut trades = Trade::new();
+        let buy = trades.add_order(Side::Buy, 100, 10).unwrap();
+        let sell = trades.add_order(Side::Sell, 200, 10).unwrap();
+
+        assert_eq!(trades.cancel(sell).unwrap().price, Price::from(200));
+        assert!(trades.sell_orders.is_empty());
+        assert!(trades.cancel(sell).is_none());
+        assert_eq!(trades.buy_orders.best().unwrap().id, buy);
+    }
+
+    #[test]
+    fn amend_into_cross_then_matches() {
+        let mut trades = Trade::new();
+        let buy = trades.add_order(Side::Buy, 100, 10).unwrap();
+        trades.add_order(Side::Sell, 110, 10).unwrap();
+        assert!(trades.execute_trade().is_none());
+
+        trades.amend(buy, 110, 10).unwrap();
+        let executed = trades.execute_trade().unwrap();
+        assert_eq!(executed.buy_order_id, buy);
+        assert_eq!(executed.price, Price::from(110));
+        // the amended buy arrived after the sell, so it is the aggressor
+        assert_eq!(executed.aggressor, Side::Buy);
+    }
+
+    #[test]
+    fn amend_unknown_order_fails() {
+        let mut trades = Trade::new();
+        assert_eq!(trades.amend(42, 100, 10), Err("order not found"));
+    }
+
+    #[test]
+    fn execute_trade_reports_ids_and_time() {
+        let mut trades = Trade::new();
+        let sell = trades.add_order(Side::Sell, 50, 10).unwrap();
+        let buy = trades.add_order(Side::Buy, 50, 4).unwrap();
+        trades.set_time(1_700_000_000_000);
+
+        let executed = trades.execute_trade().unwrap();
+        assert_eq!(
+            executed,
+            Execution {
+                buy_order_id: buy,
+                sell_order_id: sell,
+                price: Price::from(50),
+                quantity: 4,
+                aggressor: Side::Buy,
+                sequence: 1,
+                timestamp: 1_700_000_000_000,
+            }
+        );
+    }
+
+    #[test]
+    fn market_buy_sweeps_levels_at_resting_prices() {
+        let mut trades = Trade::new();
+        let first = trades.add_order(Side::Sell, 50, 5).unwrap();
+        let second = trades.add_order(Side::Sell, 52, 5).unwrap();
+        trades.add_order(Side::Sell, 55, 5).unwrap();
+
+        let market = trades.submit(Order::market(Side::Buy, 8)).unwrap();
+
+        let executed = trades.execute_trade().unwrap();
+        assert_eq!(executed.buy_order_id, market);
+        assert_eq!(executed.sell_order_id, first);
+        assert_eq!((executed.price, executed.quantity), (Price::from(50), 5));
+        assert_eq!(executed.aggressor, Side::Buy);
+
+        let executed = trades.execute_trade().unwrap();
+        assert_eq!(executed.sell_order_id, second);
+        assert_eq!((executed.price, executed.quantity), (Price::from(52), 3));
+
+        assert!(trades.execute_trade().is_none());
+        assert_eq!(trades.sell_orders.best().unwrap().quantity, 2);
+        assert!(trades.buy_orders.is_empty());
+    }
+
+    #[test]
+    fn market_sell_remainder_is_cancelled_not_rested() {
+        let mut trades = Trade::new();
+        let bid = trades.add_order(Side::Buy, 40, 3).unwrap();
+
+        let market = trades.submit(Order::market(Side::Sell, 10)).unwrap();
+
+        let executed = trades.execute_trade().unwrap();
+        assert_eq!(executed.buy_order_id, bid);
+        assert_eq!(executed.sell_order_id, market);
+        assert_eq!((executed.price, executed.quantity), (Price::from(40), 3));
+
+        assert!(trades.execute_trade().is_none());
+        assert!(trades.sell_orders.is_empty());
+        assert!(trades.get(market).is_none());
+        // a new bid is not filled by the cancelled remainder
+        trades.add_order(Side::Buy, 40, 3).unwrap();
+        assert!(trades.execute_trade().is_none());
+    }
+
+    #[test]
+    fn market_order_on_empty_book_is_rejected() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Buy, 40, 3).unwrap();
+
+        let result = trades.submit(Order::market(Side::Buy, 5));
+        assert_eq!(result, Err("no liquidity for market order"));
+        // the rejected order did not use an id
+        assert_eq!(trades.add_order(Side::Sell, 45, 1), Ok(2));
+    }
+
+    #[test]
+    fn pending_market_order_can_be_cancelled() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Sell, 50, 5).unwrap();
+        let market = trades.submit(Order::market(Side::Buy, 5)).unwrap();
+
+        assert_eq!(trades.cancel(market).unwrap().kind, OrderKind::Market);
+        assert!(trades.execute_trade().is_none());
+    }
+
+    #[test]
+    fn ioc_fills_what_it_can_and_expires_the_rest() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Sell, 50, 5).unwrap();
+        trades.add_order(Side::Sell, 60, 5).unwrap();
+
+        let ioc = Order::new(Side::Buy, 55, 8).with_time_in_force(TimeInForce::Ioc);
+        let id = trades.submit(ioc).unwrap();
+
+        let executed = trades.execute_trade().unwrap();
+        assert_eq!(
+            (executed.buy_order_id, executed.price, executed.quantity),
+            (id, Price::from(50), 5)
+        );
+        assert!(trades.execute_trade().is_none());
+        assert!(trades.buy_orders.is_empty());
+        assert_eq!(trades.sell_orders.len(), 1);
+
+        let events = trades.take_events();
+        assert_eq!(events.len(), 1);
+        let BookEvent::Expired { order, reason } = &events[0] else {
+            panic!("expected an expiry, got {:?}", events);
+        };
+        assert_eq!((order.id, order.quantity), (id, 3));
+        assert_eq!(*reason, ExpiryReason::ImmediateOrCancel);
+        assert!(trades.take_events().is_empty());
+    }
+
+    #[test]
+    fn fok_is_killed_without_partial_fill() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Sell, 50, 5).unwrap();
+        trades.add_order(Side::Sell, 60, 5).unwrap();
+
+        let fok = Order::new(Side::Buy, 55, 8).with_time_in_force(TimeInForce::Fok);
+        let id = trades.submit(fok).unwrap();
+
+        assert!(trades.execute_trade().is_none());
+        assert_eq!(trades.sell_orders.len(), 2);
+        assert_eq!(
+            trades.take_events(),
+            vec![BookEvent::Expired {
+                order: Order {
+                    id,
+                    sequence: 3,
+                    entry_sequence: 3,
+                    ..Order::new(Side::Buy, 55, 8).with_time_in_force(TimeInForce::Fok)
+                },
+                reason: ExpiryReason::FillOrKill,
+            }]
+        );
+    }
+
+    #[test]
+    fn fok_fills_in_full_across_levels() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Buy, 50, 5).unwrap();
+        trades.add_order(Side::Buy, 48, 5).unwrap();
+
+        let fok = Order::new(Side::Sell, 48, 10).with_time_in_force(TimeInForce::Fok);
+        trades.submit(fok).unwrap();
+
+        let executed: Vec<_> = std::iter::from_fn(|| trades.execute_trade()).collect();
+        assert_eq!(executed.len(), 2);
+        assert!(trades.buy_orders.is_empty());
+        assert!(trades.take_events().is_empty());
+    }
+
+    #[test]
+    fn day_orders_expire_at_end_of_session() {
+        let mut trades = Trade::new();
+        let day = Order::new(Side::Buy, 40, 5).with_time_in_force(TimeInForce::Day);
+        let day_id = trades.submit(day).unwrap();
+        let gtc_id = trades.add_order(Side::Buy, 41, 5).unwrap();
+
+        trades.end_session();
+
+        assert!(trades.get(day_id).is_none());
+        assert!(trades.get(gtc_id).is_some());
+        let events = trades.take_events();
+        assert_eq!(events.len(), 1);
+        let BookEvent::Expired { order, reason } = &events[0] else {
+            panic!("expected an expiry, got {:?}", events);
+        };
+        assert_eq!((order.id, *reason), (day_id, ExpiryReason::EndOfDay));
+    }
+
+    #[test]
+    fn gtd_orders_expire_when_the_clock_reaches_them() {
+        let mut trades = Trade::new();
+        trades.set_time(1_000);
+        let gtd = Order::new(Side::Sell, 60, 5).with_time_in_force(TimeInForce::Gtd(2_000));
+        let id = trades.submit(gtd).unwrap();
+
+        trades.set_time(1_999);
+        assert!(trades.get(id).is_some());
+        trades.set_time(2_000);
+        assert!(trades.get(id).is_none());
+
+        let events = trades.take_events();
+        let BookEvent::Expired { order, reason } = &events[0] else {
+            panic!("expected an expiry, got {:?}", events);
+        };
+        assert_eq!((order.id, *reason), (id, ExpiryReason::GoodTillDate));
+    }
+
+    #[test]
+    fn gtd_in_the_past_is_rejected() {
+        let mut trades = Trade::new();
+        trades.set_time(5_000);
+        let gtd = Order::new(Side::Sell, 60, 5).with_time_in_force(TimeInForce::Gtd(5_000));
+        assert_eq!(
+            trades.submit(gtd),
+            Err("good-till-date expiry has already passed")
+        );
+    }
+
+    #[test]
+    fn market_remainder_is_reported_as_expired() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Sell, 50, 2).unwrap();
+        let id = trades.submit(Order::market(Side::Buy, 5)).unwrap();
+
+        while trades.execute_trade().is_some() {}
+
+        let events = trades.take_events();
+        let BookEvent::Expired { order, reason } = &events[0] else {
+            panic!("expected an expiry, got {:?}", events);
+        };
+        assert_eq!((order.id, order.quantity), (id, 3));
+        assert_eq!(*reason, ExpiryReason::ImmediateOrCancel);
+    }
+
+    #[test]
+    fn stop_waits_for_last_price_then_enters_as_market() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Buy, 100, 5).unwrap();
+        trades.add_order(Side::Buy, 90, 5).unwrap();
+        let stop = trades.submit(Order::stop(Side::Sell, 95, 5)).unwrap();
+
+        // no trade yet, so nothing can trigger
+        assert!(trades.execute_trade().is_none());
+        assert_eq!(trades.stop_orders().count(), 1);
+
+        trades.add_order(Side::Sell, 100, 5).unwrap();
+        let executed = trades.execute_trade().unwrap();
+        assert_eq!(executed.price, Price::from(100));
+        assert_eq!(trades.last_price(), Some(Price::from(100)));
+        assert_eq!(trades.stop_orders().count(), 1);
+
+        trades.add_order(Side::Sell, 90, 1).unwrap();
+        assert_eq!(trades.execute_trade().unwrap().price, Price::from(90));
+        assert_eq!(trades.stop_orders().count(), 0);
+        let executed = trades.execute_trade().unwrap();
+        assert_eq!((executed.sell_order_id, executed.quantity), (stop, 4));
+        assert_eq!(executed.aggressor, Side::Sell);
+        assert!(trades.execute_trade().is_none());
+
+        match &trades.take_events()[..] {
+            [
+                BookEvent::Triggered { order },
+                BookEvent::Expired {
+                    reason: ExpiryReason::ImmediateOrCancel,
+                    ..
+                },
+            ] => {
+                assert_eq!(order.id, stop);
+                assert_eq!(order.kind, OrderKind::Market);
+            }
+            events => panic!("unexpected events: {:?}", events),
+        }
+    }
+
+    #[test]
+    fn stop_limit_rests_at_its_limit_once_triggered() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Buy, 50, 1).unwrap();
+        trades.add_order(Side::Sell, 50, 1).unwrap();
+        trades.execute_trade().unwrap();
+
+        let stop = trades
+            .submit(Order::stop_limit(Side::Buy, 50, 52, 3))
+            .unwrap();
+
+        // already at the trigger, so it activates on entry
+        assert_eq!(trades.stop_orders().count(), 0);
+        let order = trades.get(stop).unwrap();
+        assert_eq!(
+            (order.kind, order.price),
+            (OrderKind::Limit, Price::from(52))
+        );
+        assert!(order.sequence > 2);
+    }
+
+    #[test]
+    fn cascading_stops_trigger_in_a_deterministic_order() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Buy, 100, 5).unwrap();
+        trades.add_order(Side::Buy, 95, 5).unwrap();
+        trades.add_order(Side::Buy, 90, 10).unwrap();
+        let first = trades.submit(Order::stop(Side::Sell, 95, 5)).unwrap();
+        let deeper = trades.submit(Order::stop(Side::Sell, 92, 5)).unwrap();
+        let second = trades.submit(Order::stop(Side::Sell, 95, 1)).unwrap();
+
+        trades.submit(Order::market(Side::Sell, 10)).unwrap();
+        let executed: Vec<_> = std::iter::from_fn(|| trades.execute_trade())
+            .map(|execution| (execution.sell_order_id, execution.quantity))
+            .collect();
+
+        // 95 triggers `first` and `second` in arrival order; `first` trading
+        // at 90 then triggers `deeper`, which queues behind `second`.
+        assert_eq!(executed[2..], [(first, 5), (second, 1), (deeper, 4)]);
+        assert_eq!(trades.last_price(), Some(Price::from(90)));
+    }
+
+    #[test]
+    fn stop_orders_can_be_cancelled_and_expire() {
+        let mut trades = Trade::new();
+        let stop = trades.submit(Order::stop(Side::Buy, 60, 5)).unwrap();
+        let day = Order::stop(Side::Buy, 70, 5).with_time_in_force(TimeInForce::Day);
+        let day = trades.submit(day).unwrap();
+
+        assert_eq!(trades.cancel(stop).unwrap().id, stop);
+        trades.end_session();
+        assert_eq!(trades.stop_orders().count(), 0);
+        let events = trades.take_events();
+        let BookEvent::Expired { order, .. } = &events[0] else {
+            panic!("expected an expiry, got {:?}", events);
+        };
+        assert_eq!(order.id, day);
+    }
+
+    #[test]
+    fn stop_with_bad_trigger_is_rejected() {
+        let mut trades = Trade::new();
+        let result = trades.submit(Order::stop(Side::Sell, 0, 5));
+        assert_eq!(result, Err("trigger price must be positive"));
+    }
+
+    #[test]
+    fn iceberg_shows_one_slice_and_refills_at_the_back() {
+        let mut trades = Trade::new();
+        let iceberg = Order::new(Side::Sell, 50, 12).with_display_quantity(5);
+        let iceberg = trades.submit(iceberg).unwrap();
+        let behind = trades.add_order(Side::Sell, 50, 3).unwrap();
+
+        let order = trades.get(iceberg).unwrap();
+        assert_eq!((order.quantity, order.hidden_quantity), (5, 7));
+        assert_eq!(trades.sell_orders.level(Price::from(50)).unwrap().len(), 2);
+
+        trades.add_order(Side::Buy, 50, 6).unwrap();
+        let executed: Vec<_> = std::iter::from_fn(|| trades.execute_trade())
+            .map(|execution| (execution.sell_order_id, execution.quantity))
+            .collect();
+
+        // the refilled slice lost priority to the order queued behind it
+        assert_eq!(executed, vec![(iceberg, 5), (behind, 1)]);
+        let ids: Vec<_> = trades.sell_orders.iter().map(|order| order.id).collect();
+        assert_eq!(ids, vec![behind, iceberg]);
+        let order = trades.get(iceberg).unwrap();
+        assert_eq!((order.quantity, order.hidden_quantity), (5, 2));
+    }
+
+    #[test]
+    fn sweeping_an_iceberg_trades_at_its_price() {
+        let mut trades = Trade::new();
+        let iceberg = Order::new(Side::Sell, 50, 9).with_display_quantity(4);
+        trades.submit(iceberg).unwrap();
+        let buy = trades.add_order(Side::Buy, 55, 20).unwrap();
+
+        let executed: Vec<_> = std::iter::from_fn(|| trades.execute_trade()).collect();
+
+        assert_eq!(executed.len(), 3);
+        assert!(
+            executed
+                .iter()
+                .all(|execution| execution.price == Price::from(50))
+        );
+        assert!(
+            executed
+                .iter()
+                .all(|execution| execution.aggressor == Side::Buy)
+        );
+        assert!(trades.sell_orders.is_empty());
+        assert_eq!(trades.get(buy).unwrap().quantity, 11);
+    }
+
+    #[test]
+    fn fok_counts_hidden_quantity() {
+        let mut trades = Trade::new();
+        let iceberg = Order::new(Side::Buy, 50, 10).with_display_quantity(2);
+        trades.submit(iceberg).unwrap();
+
+        let fok = Order::new(Side::Sell, 50, 10).with_time_in_force(TimeInForce::Fok);
+        trades.submit(fok).unwrap();
+
+        let filled: u32 = std::iter::from_fn(|| trades.execute_trade())
+            .map(|execution| execution.quantity)
+            .sum();
+        assert_eq!(filled, 10);
+        assert!(trades.take_events().is_empty());
+    }
+
+    #[test]
+    fn iceberg_must_be_a_resting_limit_order() {
+        let mut trades = Trade::new();
+        let ioc = Order::new(Side::Buy, 50, 10)
+            .with_display_quantity(2)
+            .with_time_in_force(TimeInForce::Ioc);
+        assert_eq!(
+            trades.submit(ioc),
+            Err("iceberg orders must be resting limit orders")
+        );
+        let empty = Order::new(Side::Buy, 50, 10).with_display_quantity(0);
+        assert_eq!(
+            trades.submit(empty),
+            Err("display quantity must be positive")
+        );
+    }
+
+    #[test]
+    fn post_only_that_would_cross_is_rejected() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Sell, 50, 5).unwrap();
+
+        let post = Order::new(Side::Buy, 50, 5).with_post_only(PostOnly::Reject);
+        assert_eq!(
+            trades.submit(post),
+            Err("post-only order would cross the book")
+        );
+        assert!(trades.buy_orders.is_empty());
+
+        let post = Order::new(Side::Buy, 49, 5).with_post_only(PostOnly::Reject);
+        let id = trades.submit(post).unwrap();
+        assert_eq!(trades.get(id).unwrap().price, Price::from(49));
+        assert!(trades.execute_trade().is_none());
+    }
+
+    #[test]
+    fn post_only_reprice_moves_one_tick_away() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Buy, 40, 5).unwrap();
+
+        let post = Order::new(Side::Sell, 35, 5).with_post_only(PostOnly::Reprice);
+        let id = trades.submit(post).unwrap();
+
+        assert_eq!(trades.get(id).unwrap().price, price("40.01"));
+        assert!(trades.execute_trade().is_none());
+    }
+
+    #[test]
+    fn post_only_reprice_in_exact_price_mode() {
+        let mut trades = Trade::with_matching_mode(MatchingMode::ExactPrice);
+        trades.add_order(Side::Sell, 50, 5).unwrap();
+
+        let post = Order::new(Side::Buy, 50, 5).with_post_only(PostOnly::Reprice);
+        let id = trades.submit(post).unwrap();
+
+        assert_eq!(trades.get(id).unwrap().price, price("49.99"));
+    }
+
+    #[test]
+    fn post_only_amend_into_cross_is_rejected() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Sell, 50, 5).unwrap();
+        let post = Order::new(Side::Buy, 45, 5).with_post_only(PostOnly::Reject);
+        let id = trades.submit(post).unwrap();
+
+        assert_eq!(
+            trades.amend(id, Price::from(50), 5),
+            Err("post-only order would cross the book")
+        );
+        assert_eq!(trades.get(id).unwrap().price, Price::from(45));
+    }
+
+    #[test]
+    fn post_only_must_be_a_resting_limit_order() {
+        let mut trades = Trade::new();
+        let post = Order::market(Side::Buy, 5).with_post_only(PostOnly::Reject);
+        assert_eq!(
+            trades.submit(post),
+            Err("post-only orders must be resting limit orders")
+        );
+    }
+
+    fn peg(reference: PegReference, offset: i32, limit: Option<i32>) -> Peg {
+        Peg {
+            reference,
+            offset: Price::from(offset),
+            limit: limit.map(Price::from),
+        }
+    }
+
+    #[test]
+    fn primary_peg_follows_the_best_bid() {
+        let mut trades = Trade::new();
+        let bid = trades.add_order(Side::Buy, 100, 5).unwrap();
+        trades.add_order(Side::Sell, 110, 5).unwrap();
+
+        let pegged = Order::pegged(Side::Buy, peg(PegReference::Primary, 1, None), 5);
+        let pegged = trades.submit(pegged).unwrap();
+        assert_eq!(trades.get(pegged).unwrap().price, Price::from(101));
+
+        trades.add_order(Side::Buy, 104, 5).unwrap();
+        assert_eq!(trades.get(pegged).unwrap().price, Price::from(105));
+
+        trades.amend(bid, 102, 5).unwrap();
+        assert_eq!(trades.get(pegged).unwrap().price, Price::from(105));
+    }
+
+    #[test]
+    fn market_peg_is_capped_at_its_limit() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Buy, 90, 5).unwrap();
+        let ask = trades.add_order(Side::Sell, 100, 5).unwrap();
+
+        let pegged = Order::pegged(Side::Sell, peg(PegReference::Market, 2, Some(95)), 5);
+        let pegged = trades.submit(pegged).unwrap();
+        assert_eq!(trades.get(pegged).unwrap().price, Price::from(95));
+
+        trades.cancel(ask).unwrap();
+        trades.add_order(Side::Buy, 94, 5).unwrap();
+        assert_eq!(trades.get(pegged).unwrap().price, Price::from(96));
+    }
+
+    #[test]
+    fn midpoint_peg_rounds_away_from_the_other_side() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Buy, 100, 5).unwrap();
+        trades.add_order(Side::Sell, price("100.03"), 5).unwrap();
+
+        let buy = Order::pegged(Side::Buy, peg(PegReference::Midpoint, 0, None), 5);
+        let buy = trades.submit(buy).unwrap();
+        let sell = Order::pegged(Side::Sell, peg(PegReference::Midpoint, 0, None), 5);
+        let sell = trades.submit(sell).unwrap();
+
+        assert_eq!(trades.get(buy).unwrap().price, price("100.01"));
+        assert_eq!(trades.get(sell).unwrap().price, price("100.02"));
+        assert!(trades.execute_trade().is_none());
+    }
+
+    #[test]
+    fn repriced_peg_loses_priority() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Buy, 100, 5).unwrap();
+        trades.add_order(Side::Sell, 110, 5).unwrap();
+        let pegged = Order::pegged(Side::Buy, peg(PegReference::Primary, 0, None), 5);
+        let pegged = trades.submit(pegged).unwrap();
+        let before = trades.get(pegged).unwrap().sequence;
+
+        let bid = trades.add_order(Side::Buy, 101, 5).unwrap();
+
+        let order = trades.get(pegged).unwrap();
+        assert_eq!(order.price, Price::from(101));
+        assert!(order.sequence > before);
+        let ids: Vec<_> = trades.buy_orders.iter().map(|order| order.id).collect();
+        assert_eq!(ids[..2], [bid, pegged]);
+    }
+
+    #[test]
+    fn peg_without_reference_is_rejected() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Buy, 100, 5).unwrap();
+
+        let pegged = Order::pegged(Side::Buy, peg(PegReference::Midpoint, 0, None), 5);
+        assert_eq!(
+            trades.submit(pegged),
+            Err("no reference price for pegged order")
+        );
+    }
+
+    #[test]
+    fn pegged_price_cannot_be_amended() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Buy, 100, 5).unwrap();
+        let pegged = Order::pegged(Side::Buy, peg(PegReference::Primary, 0, None), 5);
+        let pegged = trades.submit(pegged).unwrap();
+
+        assert_eq!(
+            trades.amend(pegged, 99, 5),
+            Err("cannot change the price of a pegged order")
+        );
+        assert_eq!(trades.amend(pegged, 100, 3), Ok(()));
+    }
+
+    fn trade_at(trades: &mut Trade, price: i32) {
+        trades.add_order(Side::Buy, price, 1).unwrap();
+        trades.add_order(Side::Sell, price, 1).unwrap();
+        trades.execute_trade().unwrap();
+    }
+
+    #[test]
+    fn trailing_sell_stop_ratchets_up_only() {
+        let mut trades = Trade::new();
+        trade_at(&mut trades, 100);
+        let stop = Order::trailing_stop(Side::Sell, Trail::Amount(Price::from(5)), 3);
+        let stop = trades.submit(stop).unwrap();
+        assert_eq!(
+            trades.stop_orders().next().unwrap().trigger(),
+            Some(Price::from(95))
+        );
+
+        trade_at(&mut trades, 110);
+        assert_eq!(
+            trades.stop_orders().next().unwrap().trigger(),
+            Some(Price::from(105))
+        );
+        trade_at(&mut trades, 106);
+        assert_eq!(
+            trades.stop_orders().next().unwrap().trigger(),
+            Some(Price::from(105))
+        );
+
+        trade_at(&mut trades, 105);
+        assert_eq!(trades.stop_orders().count(), 0);
+        match &trades.take_events()[..] {
+            [BookEvent::Triggered { order }] => {
+                assert_eq!(order.id, stop);
+                assert_eq!(order.kind, OrderKind::Market);
+            }
+            events => panic!("unexpected events: {:?}", events),
+        }
+    }
+
+    #[test]
+    fn trailing_buy_stop_in_basis_points_ratchets_down_only() {
+        let mut trades = Trade::new();
+        trade_at(&mut trades, 200);
+        let stop = Order::trailing_stop(Side::Buy, Trail::BasisPoints(250), 3);
+        trades.submit(stop).unwrap();
+        assert_eq!(
+            trades.stop_orders().next().unwrap().trigger(),
+            Some(Price::from(205))
+        );
+
+        trade_at(&mut trades, 160);
+        assert_eq!(
+            trades.stop_orders().next().unwrap().trigger(),
+            Some(Price::from(164))
+        );
+        trade_at(&mut trades, 163);
+        assert_eq!(
+            trades.stop_orders().next().unwrap().trigger(),
+            Some(Price::from(164))
+        );
+    }
+
+    #[test]
+    fn trailing_stop_needs_a_last_price_and_a_trail() {
+        let mut trades = Trade::new();
+        let stop = Order::trailing_stop(Side::Sell, Trail::Amount(Price::from(5)), 3);
+        assert_eq!(
+            trades.submit(stop.clone()),
+            Err("no last price for trailing stop")
+        );
+
+        trade_at(&mut trades, 100);
+        let flat = Order::trailing_stop(Side::Sell, Trail::Amount(Price::from(0)), 3);
+        assert_eq!(trades.submit(flat), Err("trailing amount must be positive"));
+        assert!(trades.submit(stop).is_ok());
+    }
+
+    #[test]
+    fn nothing_matches_during_an_auction_phase() {
+        let mut trades = Trade::new();
+        trades.phase = TradingPhase::Auction;
+        trades.add_order(Side::Buy, 100, 10).unwrap();
+        trades.add_order(Side::Sell, 90, 10).unwrap();
+
+        assert!(trades.execute_trade().is_none());
+
+        trades.phase = TradingPhase::Continuous;
+        assert!(trades.execute_trade().is_some());
+    }
+
+    #[test]
+    fn execute_at_only_matches_orders_willing_to_trade_at_the_price() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Buy, 100, 10).unwrap();
+        trades.add_order(Side::Sell, 95, 10).unwrap();
+
+        assert!(trades.execute_at(101).is_none());
+        assert!(trades.execute_at(94).is_none());
+        let executed = trades.execute_at(98).unwrap();
+        assert_eq!((executed.price, executed.quantity), (Price::from(98), 10));
+        assert_eq!(trades.last_price(), Some(Price::from(98)));
+    }
+
+    fn self_trade_book(mode: SelfTradePrevention) -> Trade {
+        let mut trades = Trade::new();
+        trades.self_trade_prevention = mode;
+        trades
+    }
+
+    fn self_trade_cancels(trades: &mut Trade) -> Vec<(OrderId, u32)> {
+        trades
+            .take_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                BookEvent::Expired {
+                    order,
+                    reason: ExpiryReason::SelfTrade,
+                } => Some((order.id, order.quantity)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn orders_without_an_account_still_trade() {
+        let mut trades = Trade::new();
+        trades.add_order(Side::Sell, 100, 10).unwrap();
+        trades
+            .submit(Order::new(Side::Buy, 100, 10).with_account(7))
+            .unwrap();
+
+        assert!(trades.execute_trade().is_some());
+    }
+
+    #[test]
+    fn self_trade_cancel_newest_keeps_the_resting_order() {
+        let mut trades = self_trade_book(SelfTradePrevention::CancelNewest);
+        let resting = trades
+            .submit(Order::new(Side::Sell, 100, 10).with_account(7))
+            .unwrap();
+        let other = trades.add_order(Side::Sell, 101, 5).unwrap();
+        let newest = trades
+            .submit(Order::market(Side::Buy, 8).with_account(7))
+            .unwrap();
+
+        assert!(trades.execute_trade().is_none());
+        assert_eq!(self_trade_cancels(&mut trades), vec![(newest, 8)]);
+        assert!(trades.get(resting).is_some());
+        assert!(trades.get(other).is_some());
+    }
+
+    #[test]
+    fn fok_does_not_count_its_own_account() {
+        for mode in [
+            SelfTradePrevention::CancelNewest,
+            SelfTradePrevention::DecrementAndCancel,
+        ] {
+            let mut trades = self_trade_book(mode);
+            trades.add_order(Side::Sell, 100, 5).unwrap();
+            let own = trades
+                .submit(Order::new(Side::Sell, 101, 5).with_account(7))
+                .unwrap();
+            trades.add_order(Side::Sell, 102, 5).unwrap();
+            let fok = Order::new(Side::Buy, 102, 10)
+                .with_account(7)
+                .with_time_in_force(TimeInForce::Fok);
+            trades.submit(fok).unwrap();
+
+            // killed whole rather than filled at 100 and stopped at 101
+            assert!(trades.execute_trade().is_none());
+            assert_eq!(trades.sell_orders.len(), 3);
+            assert!(trades.get(own).is_some());
+        }
+    }
+
+    #[test]
+    fn fok_is_checked_once_before_it_trades() {
+        let mut trades = self_trade_book(SelfTradePrevention::CancelOldest);
+        trades.add_order(Side::Sell, 100, 5).unwrap();
+        let own = trades
+            .submit(Order::new(Side::Sell, 101, 5).with_account(7))
+            .unwrap();
+        trades.add_order(Side::Sell, 102, 5).unwrap();
+        let fok = Order::new(Side::Buy, 102, 10)
+            .with_account(7)
+            .with_time_in_force(TimeInForce::Fok);
+        trades.submit(fok).unwrap();
+
+        let executed: Vec<_> = std::iter::from_fn(|| trades.execute_trade()).collect();
+
+        let prices: Vec<Price> = executed.iter().map(|execution| execution.price).collect();
+        assert_eq!(prices, vec![Price::from(100), Price::from(102)]);
+        assert_eq!(self_trade_cancels(&mut trades), vec![(own, 5)]);
+        assert!(trades.sell_orders.is_empty());
+    }
+
+    #[test]
+    fn self_trade_cancel_oldest_lets_the_aggressor_trade_on() {
+        let mut trades = self_trade_book(SelfTradePrevention::CancelOldest);
+        let oldest = trades
+            .submit(Order::new(Side::Sell, 100, 10).with_account(7))
+            .unwrap();
+        let other = trades.add_order(Side::Sell, 100, 5).unwrap();
+        trades
+            .submit(Order::new(Side::Buy, 100, 5).with_account(7))
+            .unwrap();
+
+        let executed = trades.execute_trade().unwrap();
+
+        assert_eq!(executed.sell_order_id, other);
+        assert_eq!(self_trade_cancels(&mut trades), vec![(oldest, 10)]);
+    }
+
+    #[test]
+    fn self_trade_cancel_both() {
+        let mut trades = self_trade_book(SelfTradePrevention::CancelBoth);
+        let oldest = trades
+            .submit(Order::new(Side::Buy, 100, 10).with_account(7))
+            .unwrap();
+        let newest = trades
+            .submit(Order::new(Side::Sell, 99, 4).with_account(7))
+            .unwrap();
+
+        assert!(trades.execute_trade().is_none());
+        assert_eq!(
+            self_trade_cancels(&mut trades),
+            vec![(newest, 4), (oldest, 10)]
+        );
+        assert!(trades.buy_orders.is_empty());
+    }
+
+    #[test]
+    fn self_trade_decrement_cancels_the_smaller_order() {
+        let mut trades = self_trade_book(SelfTradePrevention::DecrementAndCancel);
+        let larger = trades
+            .submit(Order::new(Side::Buy, 100, 10).with_account(7))
+            .unwrap();
+        let smaller = trades
+            .submit(Order::new(Side::Sell, 100, 4).with_account(7))
+            .unwrap();
+
+        assert!(trades.execute_trade().is_none());
+        assert_eq!(self_trade_cancels(&mut trades), vec![(smaller, 4)]);
+        assert_eq!(trades.get(larger).unwrap().quantity, 6);
+    }
+
+    #[test]
+    fn self_trade_decrement_reaches_into_an_iceberg_reserve() {
+        let mut trades = self_trade_book(SelfTradePrevention::DecrementAndCancel);
+        let iceberg = Order::new(Side::Sell, 100, 10)
+            .with_display_quantity(3)
+            .with_account(7);
+        let iceberg = trades.submit(iceberg).unwrap();
+        trades
+            .submit(Order::market(Side::Buy, 5).with_account(7))
+            .unwrap();
+
+        assert!(trades.execute_trade().is_none());
+        let iceberg = trades.get(iceberg).unwrap();
+        assert_eq!((iceberg.quantity, iceberg.hidden_quantity), (1, 4));
+    }
+
+    #[test]
+    fn self_trade_is_prevented_in_an_auction_uncross() {
+        let mut trades = self_trade_book(SelfTradePrevention::CancelNewest);
+        trades
+            .submit(Order::new(Side::Buy, 100, 10).with_account(7))
+            .unwrap();
+        let newest = trades
+            .submit(Order::new(Side::Sell, 100, 10).with_account(7))
+            .unwrap();
+        let other = trades.add_order(Side::Sell, 100, 10).unwrap();
+
+        let executed = trades.execute_at(100).unwrap();
+
+        assert_eq!(executed.sell_order_id, other);
+        assert_eq!(self_trade_cancels(&mut trades), vec![(newest, 10)]);
+    }
+
+    fn quarter_tick_book() -> Trade {
+        let mut trades = Trade::new();
+        trades.rules = InstrumentRules {
+            tick_size: price("0.25"),
+            lot_size: 10,
+            max_quantity: Some(100),
+            ..InstrumentRules::default()
+        };
+        trades
+    }
+
+    #[test]
+    fn instrument_rules_are_enforced_on_entry() {
+        let mut trades = quarter_tick_book();
+        assert_eq!(
+            trades.add_order(Side::Buy, price("100.10"), 10),
+            Err("price is not a multiple of the tick size")
+        );
+        assert_eq!(
+            trades.add_order(Side::Buy, price("100.25"), 15),
+            Err("quantity is not a multiple of the lot size")
+        );
+        assert_eq!(
+            trades.submit(Order::market(Side::Buy, 110)),
+            Err("quantity is above the maximum quantity")
+        );
+        assert_eq!(
+            trades.add_order(Side::Buy, 0, 10),
+            Err("price must be positive")
+        );
+        assert!(trades.add_order(Side::Buy, price("100.25"), 10).is_ok());
+    }
+
+    #[test]
+    fn instrument_rules_are_enforced_on_amend() {
+        let mut trades = quarter_tick_book();
+        let id = trades.add_order(Side::Buy, 100, 10).unwrap();
+        assert_eq!(
+            trades.amend(id, price("100.10"), 10),
+            Err("price is not a multiple of the tick size")
+        );
+        assert_eq!(
+            trades.amend(id, 100, 5),
+            Err("quantity is not a multiple of the lot size")
+        );
+        assert_eq!(trades.get(id).unwrap().quantity, 10);
+        assert!(trades.amend(id, price("100.50"), 20).is_ok());
+    }
+
+    #[test]
+    fn iceberg_amend_checks_the_total_quantity() {
+        let mut trades = quarter_tick_book();
+        let iceberg = Order::new(Side::Sell, 101, 60).with_display_quantity(20);
+        let id = trades.submit(iceberg).unwrap();
+        assert_eq!(
+            trades.amend(id, 101, 110),
+            Err("quantity is above the maximum quantity")
+        );
+
+        trades.amend(id, 101, 100).unwrap();
+        let order = trades.get(id).unwrap();
+        assert_eq!((order.quantity, order.hidden_quantity), (20, 80));
+        assert_eq!(trades.level1().offer.unwrap().quantity, 20);
+    }
+
+    #[test]
+    fn non_positive_prices_rest_when_allowed() {
+        let mut trades = Trade::new();
+        trades.rules.allow_non_positive_prices = true;
+        let id = trades.add_order(Side::Buy, price("-1.50"), 5).unwrap();
+        trades.add_order(Side::Sell, 0, 5).unwrap();
+        assert!(trades.execute_trade().is_none());
+        assert_eq!(trades.get(id).unwrap().price, price("-1.50"));
+    }
+
+    #[test]
+    fn pegs_and_post_only_reprices_land_on_a_tick() {
+        let mut trades = quarter_tick_book();
+        trades.add_order(Side::Buy, 100, 10).unwrap();
+        trades.add_order(Side::Sell, price("100.75"), 10).unwrap();
+
+        let primary = |offset| Peg {
+            reference: PegReference::Primary,
+            offset: price(offset),
+            limit: None,
+        };
+        let buy = trades
+            .submit(Order::pegged(Side::Buy, primary("0.10"), 10))
+            .unwrap();
+        let sell = trades
+            .submit(Order::pegged(Side::Sell, primary("-0.10"), 10))
+            .unwrap();
+        // pegs round away from the other side
+        assert_eq!(trades.get(buy).unwrap().price, Price::from(100));
+        assert_eq!(trades.get(sell).unwrap().price, price("100.75"));
+
+        // post-only steps back a whole tick
+        let post_only = trades
+            .submit(Order::new(Side::Buy, 101, 10).with_post_only(PostOnly::Reprice))
+            .unwrap();
+        assert_eq!(trades.get(post_only).unwrap().price, price("100.50"));
     }
 }
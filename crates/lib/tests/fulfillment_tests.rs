@@ -1,50 +1,44 @@
-use lib::{Trade, Order, OrderType, fulfill_orders};
+use lib::{
+	AuctionEngine, BarKind, BookEvent, CandleSeries, Command, DepthBook, Exchange, ExpiryReason,
+	FulfillmentEngine, InstrumentRules, JournaledExchange, MatchingMode, Order, OrderBookEngine,
+	Outcome, Peg, PegReference, Price, ProRataConfig, ProRataEngine, Side, TimeInForce, Trade,
+	TradingPhase, fulfill_orders, replay,
+};
 
 #[test]
-fn integration_no_trade_when_prices_differ() {
+fn integration_executes_trade_when_prices_cross() {
 	let mut trades = Trade::new();
 
-	trades
-		.buy_orders
-		.push(Order {
-			order_type: OrderType::Buy,
-			price: 100,
-		})
-		.unwrap();
-	trades
-		.sell_orders
-		.push(Order {
-			order_type: OrderType::Sell,
-			price: 50,
-		})
-		.unwrap();
+	trades.add_order(Side::Buy, 100, 10).unwrap();
+	trades.add_order(Side::Sell, 50, 10).unwrap();
+
+	fulfill_orders(&mut trades);
+
+	assert_eq!(trades.buy_orders.len(), 0);
+	assert_eq!(trades.sell_orders.len(), 0);
+}
+
+#[test]
+fn integration_no_trade_when_prices_differ_in_exact_price_mode() {
+	let mut trades = Trade::with_matching_mode(MatchingMode::ExactPrice);
+
+	trades.add_order(Side::Buy, 100, 10).unwrap();
+	trades.add_order(Side::Sell, 50, 10).unwrap();
 
 	fulfill_orders(&mut trades);
 
 	assert_eq!(trades.buy_orders.len(), 1);
 	assert_eq!(trades.sell_orders.len(), 1);
-	assert_eq!(trades.buy_orders.as_slice()[0].price, 100);
-	assert_eq!(trades.sell_orders.as_slice()[0].price, 50);
+	assert_eq!(trades.buy_orders.best().unwrap().price, Price::from(100));
+	assert_eq!(trades.sell_orders.best().unwrap().price, Price::from(50));
 }
 
 #[test]
 fn integration_executes_trade_on_equal_price() {
 	let mut trades = Trade::new();
 
-	trades
-		.buy_orders
-		.push(Order {
-			order_type: OrderType::Buy,
-			price: 50,
-		})
-		.unwrap();
-	trades
-		.sell_orders
-		.push(Order {
-			order_type: OrderType::Sell,
-			price: 50,
-		})
-		.unwrap();
+	trades.add_order(Side::Buy, 50, 10).unwrap();
+	trades.add_order(Side::Sell, 50, 10).unwrap();
 
 	fulfill_orders(&mut trades);
 
@@ -54,37 +48,13 @@ fn integration_executes_trade_on_equal_price() {
 
 #[test]
 fn integration_removes_matching_middle_orders() {
-	let mut trades = Trade::new();
+	let mut trades = Trade::with_matching_mode(MatchingMode::ExactPrice);
 
-	trades
-		.buy_orders
-		.push(Order {
-			order_type: OrderType::Buy,
-			price: 30,
-		})
-		.unwrap();
-	trades
-		.sell_orders
-		.push(Order {
-			order_type: OrderType::Sell,
-			price: 30,
-		})
-		.unwrap();
-
-	trades
-		.buy_orders
-		.push(Order {
-			order_type: OrderType::Buy,
-			price: 50,
-		})
-		.unwrap();
-	trades
-		.sell_orders
-		.push(Order {
-			order_type: OrderType::Sell,
-			price: 60,
-		})
-		.unwrap();
+	trades.add_order(Side::Buy, 30, 10).unwrap();
+	trades.add_order(Side::Sell, 30, 10).unwrap();
+
+	trades.add_order(Side::Buy, 50, 10).unwrap();
+	trades.add_order(Side::Sell, 60, 10).unwrap();
 
 	fulfill_orders(&mut trades);
 
@@ -93,6 +63,403 @@ fn integration_removes_matching_middle_orders() {
 
 	assert_eq!(buys.len(), 1);
 	assert_eq!(sells.len(), 1);
-	assert_eq!(buys.as_slice()[0].price, 50);
-	assert_eq!(sells.as_slice()[0].price, 60);
+	assert_eq!(buys.best().unwrap().price, Price::from(50));
+	assert_eq!(sells.best().unwrap().price, Price::from(60));
+}
+
+#[test]
+fn integration_partial_fill_leaves_remainder() {
+	let mut trades = Trade::new();
+
+	trades.add_order(Side::Buy, 100, 25).unwrap();
+	trades.add_order(Side::Sell, 90, 10).unwrap();
+
+	fulfill_orders(&mut trades);
+
+	assert_eq!(trades.sell_orders.len(), 0);
+	assert_eq!(trades.buy_orders.len(), 1);
+	assert_eq!(trades.buy_orders.best().unwrap().quantity, 15);
+}
+
+#[test]
+fn integration_price_time_priority() {
+	let mut trades = Trade::new();
+
+	let worse_price = trades.add_order(Side::Sell, 51, 10).unwrap();
+	let first_at_50 = trades.add_order(Side::Sell, 50, 10).unwrap();
+	let second_at_50 = trades.add_order(Side::Sell, 50, 10).unwrap();
+	trades.add_order(Side::Buy, 51, 30).unwrap();
+
+	let mut engine = OrderBookEngine::new(&mut trades);
+	let ids: Vec<_> = engine
+		.fulfill_all()
+		.iter()
+		.map(|execution| execution.sell_order_id)
+		.collect();
+
+	assert_eq!(ids, vec![first_at_50, second_at_50, worse_price]);
+}
+
+#[test]
+fn integration_fulfill_orders_returns_all_executions() {
+	let mut trades = Trade::new();
+
+	trades.add_order(Side::Buy, 100, 10).unwrap();
+	trades.add_order(Side::Buy, 99, 10).unwrap();
+	trades.add_order(Side::Sell, 99, 15).unwrap();
+
+	let executed = fulfill_orders(&mut trades);
+
+	assert_eq!(executed.len(), 2);
+	assert_eq!(executed[0].price, Price::from(100));
+	assert_eq!(executed[1].price, Price::from(99));
+	assert_eq!(executed[0].sell_order_id, executed[1].sell_order_id);
+	assert_eq!(trades.buy_orders.best().unwrap().quantity, 5);
+}
+
+#[test]
+fn integration_market_order_fills_and_never_rests() {
+	let mut trades = Trade::new();
+
+	trades.add_order(Side::Buy, 100, 10).unwrap();
+	trades.add_order(Side::Buy, 95, 10).unwrap();
+	trades.submit(Order::market(Side::Sell, 25)).unwrap();
+
+	let executed = fulfill_orders(&mut trades);
+
+	assert_eq!(executed.len(), 2);
+	assert_eq!(executed[0].price, Price::from(100));
+	assert_eq!(executed[1].price, Price::from(95));
+	assert!(executed.iter().all(|execution| execution.aggressor == Side::Sell));
+	assert!(trades.buy_orders.is_empty());
+	assert!(trades.sell_orders.is_empty());
+}
+
+#[test]
+fn integration_ioc_remainder_is_reported_as_expired() {
+	let mut trades = Trade::new();
+
+	trades.add_order(Side::Sell, 50, 4).unwrap();
+	let ioc = Order::new(Side::Buy, 50, 10).with_time_in_force(TimeInForce::Ioc);
+	let id = trades.submit(ioc).unwrap();
+
+	let executed = fulfill_orders(&mut trades);
+
+	assert_eq!(executed.len(), 1);
+	assert!(trades.buy_orders.is_empty());
+	match &trades.take_events()[..] {
+		[BookEvent::Expired { order, reason }] => {
+			assert_eq!((order.id, order.quantity), (id, 6));
+			assert_eq!(*reason, ExpiryReason::ImmediateOrCancel);
+		}
+		events => panic!("unexpected events: {:?}", events),
+	}
+}
+
+#[test]
+fn integration_stop_loss_triggers_within_one_pass() {
+	let mut trades = Trade::new();
+
+	trades.add_order(Side::Buy, 100, 5).unwrap();
+	trades.add_order(Side::Buy, 94, 5).unwrap();
+	let stop = trades.submit(Order::stop(Side::Sell, 95, 5)).unwrap();
+	trades.submit(Order::market(Side::Sell, 6)).unwrap();
+
+	let executed = fulfill_orders(&mut trades);
+
+	// the market sell's last fill is at 94, which triggers the stop in the same pass
+	assert_eq!(executed.len(), 3);
+	assert_eq!(executed[2].sell_order_id, stop);
+	assert_eq!((executed[2].price, executed[2].quantity), (Price::from(94), 4));
+	assert_eq!(trades.last_price(), Some(Price::from(94)));
+	assert!(trades.buy_orders.is_empty());
+}
+
+#[test]
+fn integration_iceberg_shows_only_its_display_quantity() {
+	let mut trades = Trade::new();
+
+	let iceberg = Order::new(Side::Buy, 100, 30).with_display_quantity(10);
+	let id = trades.submit(iceberg).unwrap();
+	trades.add_order(Side::Sell, 100, 25).unwrap();
+
+	let executed = fulfill_orders(&mut trades);
+
+	assert_eq!(executed.len(), 3);
+	assert!(executed.iter().all(|execution| execution.buy_order_id == id));
+	let visible: u32 = trades.buy_orders.iter().map(|order| order.quantity).sum();
+	assert_eq!(visible, 5);
+	assert_eq!(trades.get(id).unwrap().hidden_quantity, 0);
+}
+
+#[test]
+fn integration_pegged_order_requotes_after_executions() {
+	let mut trades = Trade::new();
+
+	trades.add_order(Side::Buy, 100, 5).unwrap();
+	trades.add_order(Side::Buy, 98, 5).unwrap();
+	let peg = Peg {
+		reference: PegReference::Primary,
+		offset: Price::ZERO,
+		limit: None,
+	};
+	let pegged = trades.submit(Order::pegged(Side::Buy, peg, 5)).unwrap();
+	assert_eq!(trades.get(pegged).unwrap().price, Price::from(100));
+
+	// the sell takes the unpegged bid ahead of the peg, which then drops to 98
+	trades.add_order(Side::Sell, 100, 5).unwrap();
+	let executed = fulfill_orders(&mut trades);
+
+	assert_eq!(executed.len(), 1);
+	assert_ne!(executed[0].buy_order_id, pegged);
+	assert_eq!(trades.get(pegged).unwrap().price, Price::from(98));
+}
+
+#[test]
+fn integration_exchange_matches_each_book_separately() {
+	let mut exchange = Exchange::new();
+	exchange.list_instrument("AAPL", Trade::new()).unwrap();
+	exchange.list_instrument("MSFT", Trade::new()).unwrap();
+
+	exchange.submit("AAPL", Order::new(Side::Buy, 100, 10)).unwrap();
+	exchange.submit("AAPL", Order::new(Side::Sell, 100, 4)).unwrap();
+	exchange.submit("MSFT", Order::new(Side::Sell, 100, 10)).unwrap();
+
+	let executed = exchange.engine("AAPL").unwrap().fulfill_all();
+
+	assert_eq!(executed.len(), 1);
+	assert_eq!(executed[0].quantity, 4);
+	assert!(exchange.engine("MSFT").unwrap().fulfill_all().is_empty());
+	assert_eq!(exchange.book("MSFT").unwrap().sell_orders.len(), 1);
+}
+
+#[test]
+fn integration_auction_uncrosses_at_a_single_price() {
+	let mut trades = Trade::new();
+	trades.phase = TradingPhase::Auction;
+
+	trades.add_order(Side::Buy, 102, 5).unwrap();
+	trades.add_order(Side::Buy, 100, 5).unwrap();
+	trades.add_order(Side::Sell, 99, 5).unwrap();
+	trades.add_order(Side::Sell, 101, 5).unwrap();
+	assert!(fulfill_orders(&mut trades).is_empty());
+
+	let mut engine = AuctionEngine::new(&mut trades);
+	let price = engine.indicative_price().unwrap();
+	let executed = engine.fulfill_all();
+	trades.phase = TradingPhase::Continuous;
+
+	assert_eq!(executed.len(), 1);
+	assert!(executed.iter().all(|execution| execution.price == price));
+	assert_eq!(trades.buy_orders.len(), 1);
+	assert_eq!(trades.sell_orders.len(), 1);
+}
+
+#[test]
+fn integration_pro_rata_and_fifo_allocate_the_same_flow_differently() {
+	let mut fifo = Trade::new();
+	let first = fifo.add_order(Side::Buy, 100, 20).unwrap();
+	let second = fifo.add_order(Side::Buy, 100, 60).unwrap();
+	fifo.submit(Order::market(Side::Sell, 40)).unwrap();
+	let mut pro_rata = fifo.clone();
+
+	let fifo_executed = OrderBookEngine::new(&mut fifo).fulfill_all();
+	let pro_rata_executed =
+		ProRataEngine::new(&mut pro_rata, ProRataConfig::default()).fulfill_all();
+
+	let fills = |executed: &[lib::Execution]| -> Vec<_> {
+		executed.iter().map(|e| (e.buy_order_id, e.quantity)).collect()
+	};
+	assert_eq!(fills(&fifo_executed), vec![(first, 20), (second, 20)]);
+	assert_eq!(fills(&pro_rata_executed), vec![(first, 10), (second, 30)]);
+}
+
+#[test]
+fn integration_each_instrument_enforces_its_own_rules() {
+	let mut exchange = Exchange::new();
+	let mut futures = Trade::new();
+	futures.rules = InstrumentRules {
+		tick_size: "0.25".parse().unwrap(),
+		lot_size: 5,
+		..InstrumentRules::default()
+	};
+	exchange.list_instrument("ES", futures).unwrap();
+	exchange.list_instrument("AAPL", Trade::new()).unwrap();
+
+	let odd_price: Price = "101.10".parse().unwrap();
+	assert_eq!(
+		exchange.submit("ES", Order::new(Side::Buy, odd_price, 5)),
+		Err("price is not a multiple of the tick size")
+	);
+	assert_eq!(
+		exchange.submit("ES", Order::new(Side::Buy, 101, 7)),
+		Err("quantity is not a multiple of the lot size")
+	);
+	assert!(exchange.submit("AAPL", Order::new(Side::Buy, odd_price, 7)).is_ok());
+}
+
+#[test]
+fn integration_depth_views_follow_fills() {
+	let mut trades = Trade::new();
+	trades.add_order(Side::Sell, 101, 10).unwrap();
+	trades.add_order(Side::Sell, 101, 5).unwrap();
+	trades.add_order(Side::Sell, 102, 8).unwrap();
+	trades.submit(Order::market(Side::Buy, 12)).unwrap();
+
+	OrderBookEngine::new(&mut trades).fulfill_all();
+
+	let level2 = trades.level2(10);
+	assert!(level2.bids.is_empty());
+	assert_eq!(level2.offers.len(), 2);
+	assert_eq!(level2.offers[0].quantity, 3);
+	assert_eq!(level2.offers[0].order_count, 1);
+	assert_eq!(trades.level1().offer, Some(level2.offers[0]));
+	assert_eq!(trades.level3().offers.len(), 2);
+}
+
+#[test]
+fn integration_market_data_rebuilds_every_book() {
+	let mut exchange = Exchange::new();
+	exchange.list_instrument("AAA", Trade::new()).unwrap();
+	exchange.list_instrument("BBB", Trade::new()).unwrap();
+
+	exchange.submit("AAA", Order::new(Side::Buy, 99, 10)).unwrap();
+	exchange.submit("AAA", Order::new(Side::Sell, 101, 10)).unwrap();
+	exchange.submit("AAA", Order::stop(Side::Buy, 101, 5)).unwrap();
+	let peg = Peg {
+		reference: PegReference::Primary,
+		offset: Price::ZERO,
+		limit: None,
+	};
+	exchange.submit("AAA", Order::pegged(Side::Buy, peg, 3)).unwrap();
+	exchange.submit("AAA", Order::new(Side::Buy, 101, 4)).unwrap();
+	exchange.submit("BBB", Order::new(Side::Sell, 50, 7)).unwrap();
+	exchange.engine("AAA").unwrap().fulfill_all();
+
+	let mut aaa = DepthBook::new();
+	let mut bbb = DepthBook::new();
+	for (symbol, update) in exchange.take_market_data() {
+		match symbol.as_str() {
+			"AAA" => aaa.apply(&update).unwrap(),
+			_ => bbb.apply(&update).unwrap(),
+		}
+	}
+
+	let book = exchange.book("AAA").unwrap();
+	assert_eq!(aaa.level2(usize::MAX), book.level2(usize::MAX));
+	assert_eq!(aaa.last_price(), book.last_price());
+	assert_eq!(
+		bbb.level2(usize::MAX),
+		exchange.book("BBB").unwrap().level2(usize::MAX)
+	);
+}
+
+#[test]
+fn integration_tape_keeps_session_stats_per_instrument() {
+	let mut exchange = Exchange::new();
+	exchange.list_instrument("AAA", Trade::new()).unwrap();
+	exchange.list_instrument("BBB", Trade::new()).unwrap();
+	exchange.set_time(1_000);
+
+	exchange.submit("AAA", Order::new(Side::Sell, 101, 5)).unwrap();
+	exchange.submit("AAA", Order::new(Side::Sell, 102, 5)).unwrap();
+	exchange.submit("AAA", Order::market(Side::Buy, 8)).unwrap();
+	exchange.engine("AAA").unwrap().fulfill_all();
+
+	let tape = exchange.book("AAA").unwrap().tape();
+	let prints: Vec<_> = tape
+		.prints()
+		.iter()
+		.map(|print| (print.price, print.quantity, print.aggressor, print.timestamp))
+		.collect();
+	assert_eq!(
+		prints,
+		vec![
+			(Price::from(101), 5, Side::Buy, 1_000),
+			(Price::from(102), 3, Side::Buy, 1_000),
+		]
+	);
+	let stats = tape.stats();
+	assert_eq!((stats.open, stats.last), (Some(Price::from(101)), Some(Price::from(102))));
+	assert_eq!((stats.volume, stats.trade_count), (8, 2));
+	assert_eq!(stats.turnover, Price::from(811));
+	assert_eq!(exchange.book("BBB").unwrap().tape().stats().trade_count, 0);
+}
+
+#[test]
+fn integration_candles_are_built_from_engine_output() {
+	let mut trades = Trade::new();
+	let mut minutes = CandleSeries::new(BarKind::MINUTE).unwrap();
+	let mut volume = CandleSeries::new(BarKind::Volume(10)).unwrap();
+
+	for (time, price, quantity) in [(1_000, 100, 4), (30_000, 102, 4), (65_000, 101, 4)] {
+		trades.set_time(time);
+		trades.add_order(Side::Sell, price, quantity).unwrap();
+		trades.submit(Order::market(Side::Buy, quantity)).unwrap();
+		for execution in OrderBookEngine::new(&mut trades).fulfill_all() {
+			minutes.record(&execution);
+			volume.record(&execution);
+		}
+	}
+
+	let first = minutes.closed()[0];
+	assert_eq!(
+		(first.open, first.high, first.close),
+		(Price::from(100), Price::from(102), Price::from(102))
+	);
+	assert_eq!(first.volume, 8);
+	assert_eq!(minutes.current().unwrap().start, 60_000);
+	assert_eq!(volume.closed().len(), 1);
+	assert_eq!(volume.current().unwrap().volume, 2);
+	assert_eq!(
+		CandleSeries::from_executions(BarKind::MINUTE, trades.tape().prints()).unwrap(),
+		minutes
+	);
+}
+
+#[test]
+fn integration_journal_replays_to_the_same_books_and_executions() {
+	let mut journal = Vec::new();
+	let mut live = JournaledExchange::new(&mut journal);
+	let mut executed = Vec::new();
+	let symbol = || "AAA".to_string();
+	let commands = [
+		Command::List {
+			symbol: symbol(),
+			rules: InstrumentRules::default(),
+		},
+		Command::SetTime(10),
+		Command::Submit {
+			symbol: symbol(),
+			order: Order::new(Side::Sell, 101, 10).with_display_quantity(4),
+		},
+		Command::Submit {
+			symbol: symbol(),
+			order: Order::stop(Side::Buy, 101, 3),
+		},
+		Command::Submit {
+			symbol: symbol(),
+			order: Order::market(Side::Buy, 6),
+		},
+		Command::Fulfill { symbol: symbol() },
+		Command::Cancel {
+			symbol: symbol(),
+			id: 1,
+		},
+	];
+	for command in commands {
+		if let Outcome::Executed(executions) = live.execute(command).unwrap() {
+			executed.extend(executions);
+		}
+	}
+	let live_book = format!("{:?}", live.exchange());
+	drop(live);
+
+	let replayed = replay(&journal[..]).unwrap();
+
+	assert_eq!(format!("{:?}", replayed.exchange), live_book);
+	let replayed_executions: Vec<_> =
+		replayed.executions.into_iter().map(|(_, execution)| execution).collect();
+	assert_eq!(replayed_executions, executed);
+	assert_eq!(executed.len(), 4);
 }
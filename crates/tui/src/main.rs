@@ -1,34 +1,194 @@
-use std::io;
-use trading_lib::{FulfillmentEngine, OrderBookEngine, Trade};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader};
+use std::time::{SystemTime, UNIX_EPOCH};
+use trading_lib::{
+    BookEvent, Command, Execution, InstrumentRules, JournaledExchange, Order, OrderId, Outcome,
+    Peg, PegReference, PostOnly, Price, PriceLevel, Side, TimeInForce, Timestamp, Trade,
+    TradingPhase, Trail, replay, uncrossing_price,
+};
+
+/// Journal used when `TRADER_JOURNAL` is not set.
+const DEFAULT_JOURNAL: &str = "trader.journal";
 
 fn main() {
-    let mut unexecuted_trades: Trade = Trade::new();
+    let mut exchange = open_journal();
+    let mut symbol = select_instrument(&mut exchange);
     let mut is_valid_menu = false;
 
     loop {
-        let menu_input = display_menu();
+        let menu_input = display_menu(&symbol);
+
+        // These work on the whole exchange rather than the selected book.
+        match menu_input.as_str() {
+            "11" => {
+                end_session(&mut exchange);
+                continue;
+            }
+            "12" => {
+                symbol = select_instrument(&mut exchange);
+                continue;
+            }
+            _ => {}
+        }
+        let unexecuted_trades = &mut Book {
+            exchange: &mut exchange,
+            symbol: &symbol,
+        };
 
         match menu_input.as_str() {
             "1" => is_valid_menu = true,
             "2" => is_valid_menu = true,
-            "3" => break,
+            "3" => market_order(Side::Buy, unexecuted_trades),
+            "4" => market_order(Side::Sell, unexecuted_trades),
+            "5" => stop_order(false, unexecuted_trades),
+            "6" => stop_order(true, unexecuted_trades),
+            "7" => trailing_stop_order(unexecuted_trades),
+            "8" => pegged_order(unexecuted_trades),
+            "9" => cancel_order(unexecuted_trades),
+            "10" => amend_order(unexecuted_trades),
+            "13" => start_auction(unexecuted_trades),
+            "14" => uncross_auction(unexecuted_trades),
+            "15" => break,
             _ => println!(" I don't understand, try again"),
         }
 
         if is_valid_menu {
-            let price: i32 = get_price_input();
-            fulfill_orders(&menu_input, price, &mut unexecuted_trades);
+            let price: Price = get_price_input();
+            let quantity: u32 = get_quantity_input();
+            fulfill_orders(&menu_input, price, quantity, unexecuted_trades);
             is_valid_menu = false;
         }
     }
 }
 
-fn display_menu() -> String {
+/// Replays the journal left by earlier runs, if there is one, and keeps
+/// appending to it.
+fn open_journal() -> JournaledExchange<File> {
+    let path = std::env::var("TRADER_JOURNAL").unwrap_or_else(|_| DEFAULT_JOURNAL.to_string());
+    let replayed = match File::open(&path) {
+        Ok(file) => replay(BufReader::new(file)).expect("The journal could not be replayed"),
+        Err(_) => replay(io::empty()).expect("An empty journal always replays"),
+    };
+    println!(
+        " Journal {}: recovered {} instruments and {} executions",
+        path,
+        replayed.exchange.instruments().count(),
+        replayed.executions.len()
+    );
+    let journal = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .expect("Failed to open the journal");
+    // Drops a record torn by a crash.
+    journal
+        .set_len(replayed.journal_len())
+        .expect("Failed to repair the journal");
+    JournaledExchange::resume(replayed, journal).expect("Failed to write the journal")
+}
+
+/// The selected instrument. Every change to it goes through the journal, so
+/// it survives a restart.
+struct Book<'a> {
+    exchange: &'a mut JournaledExchange<File>,
+    symbol: &'a str,
+}
+
+impl Book<'_> {
+    fn trades(&self) -> &Trade {
+        self.exchange
+            .exchange()
+            .book(self.symbol)
+            .expect("the selected instrument is listed")
+    }
+
+    fn execute(&mut self, command: Command) -> Outcome {
+        self.exchange
+            .execute(command)
+            .expect("Failed to write the journal")
+    }
+
+    /// Enters `order` at the current time, so that its expiry and any
+    /// trades it makes are stamped correctly.
+    fn submit(&mut self, order: Order) -> Result<OrderId, String> {
+        self.set_time(now());
+        let symbol = self.symbol.to_string();
+        match self.execute(Command::Submit { symbol, order }) {
+            Outcome::Accepted(id) => Ok(id),
+            Outcome::Rejected(reason) => Err(reason),
+            outcome => unreachable!("submit returned {:?}", outcome),
+        }
+    }
+
+    /// Runs `command` for its effect: `Err` holds the rejection reason.
+    fn change(&mut self, command: Command) -> Result<(), String> {
+        match self.execute(command) {
+            Outcome::Rejected(reason) => Err(reason),
+            _ => Ok(()),
+        }
+    }
+
+    fn cancel(&mut self, id: OrderId) -> Result<(), String> {
+        let symbol = self.symbol.to_string();
+        self.change(Command::Cancel { symbol, id })
+    }
+
+    fn amend(&mut self, id: OrderId, price: Price, quantity: u32) -> Result<(), String> {
+        let symbol = self.symbol.to_string();
+        self.change(Command::Amend {
+            symbol,
+            id,
+            price,
+            quantity,
+        })
+    }
+
+    /// Sets the clock of every book.
+    fn set_time(&mut self, time: Timestamp) {
+        self.execute(Command::SetTime(time));
+    }
+
+    fn set_phase(&mut self, phase: TradingPhase) {
+        let symbol = self.symbol.to_string();
+        self.execute(Command::SetPhase { symbol, phase });
+    }
+
+    fn executions(&mut self, command: Command) -> Vec<Execution> {
+        match self.execute(command) {
+            Outcome::Executed(executions) => executions,
+            _ => Vec::new(),
+        }
+    }
+
+    fn fulfill(&mut self) -> Vec<Execution> {
+        let symbol = self.symbol.to_string();
+        self.executions(Command::Fulfill { symbol })
+    }
+
+    fn uncross(&mut self) -> Vec<Execution> {
+        let symbol = self.symbol.to_string();
+        self.executions(Command::Uncross { symbol })
+    }
+}
+
+fn display_menu(symbol: &str) -> String {
     let mut input = String::new();
-    println!("Basic trader - Menu options");
+    println!("Basic trader - {} - Menu options", symbol);
     println!("  1. Enter Buy Order ");
     println!("  2. Enter Sell Order ");
-    println!("  3. Exit ");
+    println!("  3. Enter Market Buy Order ");
+    println!("  4. Enter Market Sell Order ");
+    println!("  5. Enter Stop Order ");
+    println!("  6. Enter Stop-Limit Order ");
+    println!("  7. Enter Trailing Stop Order ");
+    println!("  8. Enter Pegged Order ");
+    println!("  9. Cancel Order ");
+    println!("  10. Amend Order ");
+    println!("  11. End Session ");
+    println!("  12. Select Instrument ");
+    println!("  13. Start Auction ");
+    println!("  14. Uncross Auction ");
+    println!("  15. Exit ");
 
     io::stdin()
         .read_line(&mut input)
@@ -39,41 +199,538 @@ fn display_menu() -> String {
     value.to_string()
 }
 
-fn get_price_input() -> i32 {
+/// Asks for an instrument symbol, listing a new instrument if needed, and
+/// returns it.
+fn select_instrument(exchange: &mut JournaledExchange<File>) -> String {
+    loop {
+        let mut input = String::new();
+        let listed: Vec<&str> = exchange.exchange().instruments().collect();
+        println!(" Listed instruments: {:?}", listed);
+        println!(" Enter an instrument symbol (a new one is listed): ");
+
+        io::stdin()
+            .read_line(&mut input)
+            .expect("Failed to read line");
+
+        let symbol = input.trim().to_uppercase();
+        println!("     Instrument entered: {}", symbol);
+        if exchange.exchange().book(&symbol).is_some() {
+            return symbol;
+        }
+        let command = Command::List {
+            symbol: symbol.clone(),
+            rules: get_rules_input(),
+        };
+        match exchange
+            .execute(command)
+            .expect("Failed to write the journal")
+        {
+            Outcome::Rejected(err) => println!(" Cannot list instrument: {}", err),
+            _ => return symbol,
+        }
+    }
+}
+
+fn get_rules_input() -> InstrumentRules {
     let mut input = String::new();
-    println!(" Enter an integer price: ");
+    println!(" Enter a tick size and lot size (e.g. 0.25 10), or nothing for the defaults: ");
+
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+
+    let mut rules = InstrumentRules::default();
+    let mut fields = input.split_whitespace();
+    if let Some(tick_size) = fields.next() {
+        rules.tick_size = tick_size
+            .parse()
+            .expect("The tick size was not a valid price");
+    }
+    if let Some(lot_size) = fields.next() {
+        rules.lot_size = lot_size
+            .parse()
+            .expect("The lot size was not a valid number");
+    }
+    println!("     Rules entered: {:?}", rules);
+    rules
+}
+
+fn get_price_input() -> Price {
+    let mut input = String::new();
+    println!(" Enter a price (e.g. 101.25): ");
 
     io::stdin()
         .read_line(&mut input)
         .expect("Failed to read line");
 
     println!("     Price entered: {}", input);
-    let price: i32 = input
+    let price: Price = input
         .trim()
         .parse()
-        .expect("The input string was not a valid i32 number");
+        .expect("The input string was not a valid price");
     price
 }
 
-fn fulfill_orders(menu_input: &str, price: i32, trades: &mut Trade) {
-    match menu_input {
-        "1" => trades
-            .buy_orders
-            .add_order(price)
-            .expect("failed to add buy order"),
-        "2" => trades
-            .sell_orders
-            .add_order(price)
-            .expect("failed to add sell order"),
-        _ => (),
+fn get_trigger_input() -> Price {
+    let mut input = String::new();
+    println!(" Enter a trigger price: ");
+
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+
+    println!("     Trigger entered: {}", input);
+    input
+        .trim()
+        .parse()
+        .expect("The input string was not a valid price")
+}
+
+fn get_side_input() -> Side {
+    let mut input = String::new();
+    println!(" Enter a side (B or S): ");
+
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+
+    println!("     Side entered: {}", input);
+    match input.trim().to_uppercase().as_str() {
+        "B" => Side::Buy,
+        "S" => Side::Sell,
+        _ => panic!("The input string was not a valid side"),
+    }
+}
+
+fn get_peg_input() -> Peg {
+    let mut input = String::new();
+    println!(" Enter a peg (P = primary, M = market, X = midpoint): ");
+
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+
+    println!("     Peg entered: {}", input);
+    let reference = match input.trim().to_uppercase().as_str() {
+        "P" => PegReference::Primary,
+        "M" => PegReference::Market,
+        "X" => PegReference::Midpoint,
+        _ => panic!("The input string was not a valid peg"),
+    };
+
+    input.clear();
+    println!(" Enter a price offset: ");
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+    println!("     Offset entered: {}", input);
+    let offset: Price = input
+        .trim()
+        .parse()
+        .expect("The input string was not a valid price");
+
+    input.clear();
+    println!(" Enter a limit price, blank for none: ");
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+    println!("     Limit entered: {}", input);
+    let limit = match input.trim() {
+        "" => None,
+        value => Some(
+            value
+                .parse()
+                .expect("The input string was not a valid price"),
+        ),
+    };
+
+    Peg {
+        reference,
+        offset,
+        limit,
+    }
+}
+
+fn get_trail_input() -> Trail {
+    let mut input = String::new();
+    println!(" Enter a trail as a price or basis points (e.g. 0.5 or 150bp): ");
+
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+
+    println!("     Trail entered: {}", input);
+    input
+        .parse()
+        .expect("The input string was not a valid trail")
+}
+
+fn get_quantity_input() -> u32 {
+    let mut input = String::new();
+    println!(" Enter a quantity: ");
+
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+
+    println!("     Quantity entered: {}", input);
+    let quantity: u32 = input
+        .trim()
+        .parse()
+        .expect("The input string was not a valid u32 number");
+    quantity
+}
+
+fn get_order_id_input() -> OrderId {
+    let mut input = String::new();
+    println!(" Enter an order id: ");
+
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+
+    println!("     Order id entered: {}", input);
+    let id: OrderId = input
+        .trim()
+        .parse()
+        .expect("The input string was not a valid order id");
+    id
+}
+
+fn get_time_in_force_input() -> TimeInForce {
+    let mut input = String::new();
+    println!(" Enter a time in force (GTC, IOC, FOK, DAY, GTD), blank for GTC: ");
+
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+
+    println!("     Time in force entered: {}", input);
+    match input.trim().to_uppercase().as_str() {
+        "" | "GTC" => TimeInForce::Gtc,
+        "IOC" => TimeInForce::Ioc,
+        "FOK" => TimeInForce::Fok,
+        "DAY" => TimeInForce::Day,
+        "GTD" => TimeInForce::Gtd(now() + get_expiry_seconds_input() * 1000),
+        _ => panic!("The input string was not a valid time in force"),
+    }
+}
+
+fn get_display_quantity_input() -> Option<u32> {
+    let mut input = String::new();
+    println!(" Enter a display quantity, blank to show the whole order: ");
+
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+
+    println!("     Display quantity entered: {}", input);
+    match input.trim() {
+        "" => None,
+        value => Some(
+            value
+                .parse()
+                .expect("The input string was not a valid u32 number"),
+        ),
+    }
+}
+
+fn get_post_only_input() -> Option<PostOnly> {
+    let mut input = String::new();
+    println!(" Post-only? (R to reject if it would cross, P to reprice, blank for no): ");
+
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+
+    println!("     Post-only entered: {}", input);
+    match input.trim().to_uppercase().as_str() {
+        "" | "N" => None,
+        "R" => Some(PostOnly::Reject),
+        "P" => Some(PostOnly::Reprice),
+        _ => panic!("The input string was not a valid post-only choice"),
+    }
+}
+
+fn get_expiry_seconds_input() -> Timestamp {
+    let mut input = String::new();
+    println!(" Enter seconds until expiry: ");
+
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+
+    println!("     Seconds entered: {}", input);
+    input
+        .trim()
+        .parse()
+        .expect("The input string was not a valid number of seconds")
+}
+
+fn fulfill_orders(menu_input: &str, price: Price, quantity: u32, book: &mut Book) {
+    let side = match menu_input {
+        "1" => Side::Buy,
+        "2" => Side::Sell,
+        _ => return,
+    };
+    let mut order = Order::new(side, price, quantity).with_time_in_force(get_time_in_force_input());
+    if let Some(display_quantity) = get_display_quantity_input() {
+        order = order.with_display_quantity(display_quantity);
+    }
+    if let Some(post_only) = get_post_only_input() {
+        order = order.with_post_only(post_only);
+    }
+    match book.submit(order) {
+        Ok(id) => {
+            println!(" Order {} entered", id);
+            run_engine(book);
+        }
+        Err(err) => println!(" Order rejected: {}", err),
+    }
+}
+
+fn market_order(side: Side, book: &mut Book) {
+    let quantity: u32 = get_quantity_input();
+    match book.submit(Order::market(side, quantity)) {
+        Ok(id) => {
+            println!(" Market order {} entered", id);
+            run_engine(book);
+        }
+        Err(err) => println!(" Market order rejected: {}", err),
+    }
+}
+
+fn stop_order(is_stop_limit: bool, book: &mut Book) {
+    let side = get_side_input();
+    let trigger = get_trigger_input();
+    let order = if is_stop_limit {
+        let price = get_price_input();
+        Order::stop_limit(side, trigger, price, get_quantity_input())
+    } else {
+        Order::stop(side, trigger, get_quantity_input())
+    };
+    match book.submit(order) {
+        Ok(id) => {
+            println!(" Stop order {} entered", id);
+            // It may have triggered straight away.
+            run_engine(book);
+        }
+        Err(err) => println!(" Stop order rejected: {}", err),
     }
+}
+
+fn trailing_stop_order(book: &mut Book) {
+    let side = get_side_input();
+    let trail = get_trail_input();
+    let quantity = get_quantity_input();
+    match book.submit(Order::trailing_stop(side, trail, quantity)) {
+        Ok(id) => println!(" Trailing stop order {} entered", id),
+        Err(err) => println!(" Trailing stop order rejected: {}", err),
+    }
+}
+
+fn pegged_order(book: &mut Book) {
+    let side = get_side_input();
+    let peg = get_peg_input();
+    let quantity = get_quantity_input();
+    match book.submit(Order::pegged(side, peg, quantity)) {
+        Ok(id) => {
+            println!(
+                " Pegged order {} entered at {}",
+                id,
+                book.trades()
+                    .get(id)
+                    .map_or(Price::ZERO, |order| order.price)
+            );
+            run_engine(book);
+        }
+        Err(err) => println!(" Pegged order rejected: {}", err),
+    }
+}
+
+fn cancel_order(book: &mut Book) {
+    let id = get_order_id_input();
+    match book.cancel(id) {
+        Ok(()) => println!(" Cancelled order {}", id),
+        Err(err) => println!(" Cancel failed: {}", err),
+    }
+}
+
+fn amend_order(book: &mut Book) {
+    let id = get_order_id_input();
+    let price: Price = get_price_input();
+    let quantity: u32 = get_quantity_input();
+    match book.amend(id, price, quantity) {
+        Ok(()) => {
+            println!(" Order {} amended", id);
+            // An amended price can cross the book.
+            run_engine(book);
+        }
+        Err(err) => println!(" Amend failed: {}", err),
+    }
+}
+
+fn end_session(exchange: &mut JournaledExchange<File>) {
+    exchange
+        .execute(Command::EndSession)
+        .expect("Failed to write the journal");
+    println!(" Session ended");
+    for (symbol, event) in exchange.take_events() {
+        print_event(&symbol, event);
+    }
+}
+
+fn now() -> Timestamp {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before 1970")
+        .as_millis() as Timestamp
+}
+
+fn start_auction(book: &mut Book) {
+    book.set_phase(TradingPhase::Auction);
+    println!(" Auction started: orders are collected without matching");
+}
+
+fn uncross_auction(book: &mut Book) {
+    if book.trades().phase != TradingPhase::Auction {
+        println!(" No auction in progress");
+        return;
+    }
+    book.set_time(now());
+    let trades = book.trades();
+    match uncrossing_price(trades, trades.last_price()) {
+        Some(price) => println!(" Uncrossing at {}", price),
+        None => println!(" Book is not crossed, nothing to uncross"),
+    }
+    for execution in book.uncross() {
+        print_execution(&execution);
+    }
+    book.set_phase(TradingPhase::Continuous);
+    // Orders that could not take part, such as market orders, match now.
+    run_engine(book);
+}
+
+fn run_engine(book: &mut Book) {
+    book.set_time(now());
+    println!(" Fulfilling");
+    print_book(book.trades());
+    for execution in book.fulfill() {
+        print_execution(&execution);
+    }
+    println!(" After fulfillment");
+    print_book(book.trades());
+    print_stats(book.trades());
+    print_events(book);
+}
+
+/// Levels shown on each side of the depth ladder.
+const DEPTH: usize = 5;
+
+fn print_book(trades: &Trade) {
+    let level1 = trades.level1();
+    let show = |level: Option<PriceLevel>| {
+        level.map_or("-".to_string(), |level| {
+            format!("{} @ {}", level.quantity, level.price)
+        })
+    };
+    println!(
+        "   Best bid {} | best offer {}",
+        show(level1.bid),
+        show(level1.offer)
+    );
+
+    let level2 = trades.level2(DEPTH);
+    println!(
+        "   {:>10} {:>12} | {:<12} {:<10}",
+        "Bid qty", "Bid", "Offer", "Offer qty"
+    );
+    for row in 0..level2.bids.len().max(level2.offers.len()) {
+        let (bid_quantity, bid) = level2
+            .bids
+            .get(row)
+            .map_or((String::new(), String::new()), |level| {
+                (level.quantity.to_string(), level.price.to_string())
+            });
+        let (offer, offer_quantity) = level2
+            .offers
+            .get(row)
+            .map_or((String::new(), String::new()), |level| {
+                (level.price.to_string(), level.quantity.to_string())
+            });
+        println!(
+            "   {:>10} {:>12} | {:<12} {:<10}",
+            bid_quantity, bid, offer, offer_quantity
+        );
+    }
+
+    let level3 = trades.level3();
+    for order in level3.bids.iter().chain(&level3.offers) {
+        println!(
+            "   Order {}: {:?} {} @ {} (sequence {})",
+            order.id, order.side, order.quantity, order.price, order.sequence
+        );
+    }
+}
+
+fn print_stats(trades: &Trade) {
+    let stats = trades.tape().stats();
+    let show = |price: Option<Price>| price.map_or("-".to_string(), |price| price.to_string());
+    println!(
+        "   Session: last {} open {} high {} low {} | volume {} turnover {} trades {} vwap {}",
+        show(stats.last),
+        show(stats.open),
+        show(stats.high),
+        show(stats.low),
+        stats.volume,
+        stats.turnover,
+        stats.trade_count,
+        show(stats.vwap())
+    );
+}
+
+fn print_execution(execution: &Execution) {
+    println!(
+        "Executed #{}: {} @ {} (buy {} / sell {}, {:?} aggressor)",
+        execution.sequence,
+        execution.quantity,
+        execution.price,
+        execution.buy_order_id,
+        execution.sell_order_id,
+        execution.aggressor
+    );
+}
+
+/// Prints the events of every book, naming the book unless it is the
+/// selected one.
+fn print_events(book: &mut Book) {
+    for (symbol, event) in book.exchange.take_events() {
+        let symbol = if symbol == book.symbol { "" } else { &symbol };
+        print_event(symbol, event);
+    }
+}
 
-    println!(" Fulfilling\n   Trades {:?}", trades);
-    // Use the trait-based engine instead of the free function.
-    let mut engine = OrderBookEngine::new(trades);
-    let trade = engine.fulfill();
-    if let Some(trade) = trade {
-        println!("Executed trade: {:?}", trade);
+/// Prints a book event, prefixed with `symbol` unless it is empty.
+fn print_event(symbol: &str, event: BookEvent) {
+    let symbol = if symbol.is_empty() {
+        String::new()
+    } else {
+        format!("{}: ", symbol)
+    };
+    match event {
+        BookEvent::Expired { order, reason } => println!(
+            "{}Expired order {} ({} left, {:?})",
+            symbol,
+            order.id,
+            order.total_quantity(),
+            reason
+        ),
+        BookEvent::Triggered { order } => println!(
+            "{}Triggered stop order {} ({} {:?})",
+            symbol,
+            order.id,
+            order.total_quantity(),
+            order.kind
+        ),
     }
-    println!(" After fulfillment\n   Trades {:?}", trades);
 }